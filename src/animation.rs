@@ -0,0 +1,748 @@
+use crate::camera_controller::CameraController;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::keyframed::Keyframed;
+use crate::material::*;
+use crate::math::quat::*;
+use crate::math::vec3::{cross, dot, Vec3};
+use crate::medium::Medium;
+use crate::microfacet::conductor_preset;
+use crate::quad::Quad;
+use crate::spectrum::Ior;
+use crate::texture::*;
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Interpolation {
+    Linear,
+    CatmullRom,
+}
+
+impl Interpolation {
+    pub fn from_name(name: &str) -> Option<Interpolation> {
+        return match name {
+            "linear" => Some(Interpolation::Linear),
+            "catmull-rom" => Some(Interpolation::CatmullRom),
+            _ => None,
+        };
+    }
+}
+
+pub trait Interpolate: Copy {
+    fn lerp(a: Self, b: Self, t: f32) -> Self;
+    // Uniform Catmull-Rom spline between p1 and p2.
+    fn catmull_rom(p0: Self, p1: Self, p2: Self, p3: Self, t: f32) -> Self;
+}
+
+impl Interpolate for f32 {
+    fn lerp(a: f32, b: f32, t: f32) -> f32 {
+        return a + (b - a) * t;
+    }
+
+    fn catmull_rom(p0: f32, p1: f32, p2: f32, p3: f32, t: f32) -> f32 {
+        let t2 = t * t;
+        let t3 = t2 * t;
+        return 0.5
+            * (2.0 * p1
+                + (p2 - p0) * t
+                + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+                + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3);
+    }
+}
+
+impl Interpolate for Vec3 {
+    fn lerp(a: Vec3, b: Vec3, t: f32) -> Vec3 {
+        return a + (b - a) * t;
+    }
+
+    fn catmull_rom(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, t: f32) -> Vec3 {
+        return Vec3::new(
+            f32::catmull_rom(p0.x, p1.x, p2.x, p3.x, t),
+            f32::catmull_rom(p0.y, p1.y, p2.y, p3.y, t),
+            f32::catmull_rom(p0.z, p1.z, p2.z, p3.z, t),
+        );
+    }
+}
+
+// Keys about the same axis turn through the angle between them, however
+// large, so a turntable keyed from 0 to 360 degrees goes all the way round.
+// Keys about different axes are slerped along the shorter arc, and rotations
+// are never splined, that isn't worth it here.
+impl Interpolate for AxisAngle {
+    fn lerp(a: AxisAngle, b: AxisAngle, t: f32) -> AxisAngle {
+        let alignment = dot(a.axis, b.axis);
+        if alignment > 0.9999 {
+            return AxisAngle::new(a.axis, f32::lerp(a.degrees, b.degrees, t));
+        }
+        if alignment < -0.9999 {
+            return AxisAngle::new(a.axis, f32::lerp(a.degrees, -b.degrees, t));
+        }
+        return AxisAngle::from_quat(slerp(a.to_quat(), b.to_quat(), t));
+    }
+
+    fn catmull_rom(
+        _p0: AxisAngle,
+        p1: AxisAngle,
+        p2: AxisAngle,
+        _p3: AxisAngle,
+        t: f32,
+    ) -> AxisAngle {
+        return AxisAngle::lerp(p1, p2, t);
+    }
+}
+
+// Keys sorted by time in seconds. Before the first key and after the last one
+// the value holds still.
+#[derive(Clone)]
+pub struct Track<T: Interpolate> {
+    keys: Vec<(f32, T)>,
+    pub interpolation: Interpolation,
+}
+
+impl<T: Interpolate> Track<T> {
+    pub fn new() -> Self {
+        Self {
+            keys: Vec::new(),
+            interpolation: Interpolation::Linear,
+        }
+    }
+
+    pub fn add(&mut self, time: f32, value: T) {
+        let i = self.keys.partition_point(|(t, _)| *t <= time);
+        self.keys.insert(i, (time, value));
+    }
+
+    pub fn at(&self, time: f32) -> Option<T> {
+        let first = self.keys.first()?;
+        let last = self.keys.last()?;
+        if time <= first.0 {
+            return Some(first.1);
+        }
+        if time >= last.0 {
+            return Some(last.1);
+        }
+
+        // keys[i] is the last key at or before time, and keys[i + 1] exists.
+        let i = self.keys.partition_point(|(t, _)| *t <= time) - 1;
+        let (t1, p1) = self.keys[i];
+        let (t2, p2) = self.keys[i + 1];
+        let t = (time - t1) / (t2 - t1);
+
+        return Some(match self.interpolation {
+            Interpolation::Linear => T::lerp(p1, p2, t),
+            Interpolation::CatmullRom => {
+                let p0 = if i > 0 { self.keys[i - 1].1 } else { p1 };
+                let p3 = if i + 2 < self.keys.len() {
+                    self.keys[i + 2].1
+                } else {
+                    p2
+                };
+                T::catmull_rom(p0, p1, p2, p3, t)
+            }
+        });
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum MaterialKind {
+    Lambertian,
+    Metal,
+    Dielectric,
+    // One of the conductor presets, by name.
+    Conductor(&'static str),
+    RoughDielectric,
+    Principled,
+    Subsurface,
+}
+
+// Layer over a material's surface.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum CoatingKind {
+    Clear,
+    Film,
+}
+
+// Scalar material parameters that can be keyed.
+const SCALAR_PARAMETERS: [&str; 18] = [
+    "fuzz",
+    "ir",
+    "roughness",
+    "metallic",
+    "specular",
+    "specular-tint",
+    "sheen",
+    "clearcoat",
+    "transmission",
+    "coat-ir",
+    "film-thickness",
+    "substrate-ir",
+    "anisotropy",
+    "bump-scale",
+    "opacity",
+    "roughness-u",
+    "roughness-v",
+    "rotation",
+];
+
+// Parameters of the principled material that can be textured instead, and
+// the normal and bump maps and opacity masks any material can take.
+const TEXTURE_PARAMETERS: [&str; 12] = [
+    "albedo",
+    "emission",
+    "metallic",
+    "roughness",
+    "specular",
+    "specular-tint",
+    "sheen",
+    "clearcoat",
+    "transmission",
+    "normal",
+    "bump",
+    "opacity",
+];
+
+// Material parameters are sampled once per frame, at the frame's start.
+#[derive(Clone)]
+struct MaterialTracks {
+    kind: MaterialKind,
+    albedo: Track<Vec3>,
+    emission: Track<Vec3>,
+    mean_free_path: Track<Vec3>,
+    scalars: Vec<(&'static str, Track<f32>)>,
+    textures: Vec<(&'static str, Arc<dyn Texture>)>,
+    // Wavelength dependent index of a dielectric, in place of ir.
+    dispersion: Option<Ior>,
+    coating: Option<CoatingKind>,
+}
+
+impl MaterialTracks {
+    fn new(kind: MaterialKind) -> Self {
+        Self {
+            kind,
+            albedo: Track::new(),
+            emission: Track::new(),
+            mean_free_path: Track::new(),
+            scalars: SCALAR_PARAMETERS
+                .iter()
+                .map(|name| (*name, Track::new()))
+                .collect(),
+            textures: Vec::new(),
+            dispersion: None,
+            coating: None,
+        }
+    }
+
+    fn scalar(&mut self, name: &str) -> Option<&mut Track<f32>> {
+        return self
+            .scalars
+            .iter_mut()
+            .find(|(n, _)| *n == name)
+            .map(|(_, track)| track);
+    }
+
+    fn scalar_at(&self, name: &str, time: f32, default: f32) -> f32 {
+        return self
+            .scalars
+            .iter()
+            .find(|(n, _)| *n == name)
+            .and_then(|(_, track)| track.at(time))
+            .unwrap_or(default);
+    }
+
+    // The parameter's texture if it has one, else its keyed value.
+    fn texture_at(&self, name: &str, time: f32, default: f32) -> Arc<dyn Texture> {
+        if let Some((_, texture)) = self.textures.iter().find(|(n, _)| *n == name) {
+            return texture.clone();
+        }
+        let value = self.scalar_at(name, time, default);
+        return Arc::new(SolidColor::new(Vec3::new(value, value, value)));
+    }
+
+    fn build(&self, time: f32) -> Arc<dyn Material> {
+        let albedo = self.albedo.at(time).unwrap_or(Vec3::new(0.5, 0.5, 0.5));
+        let roughness = self.scalar_at("roughness", time, 0.2);
+        let ir = self.scalar_at("ir", time, 1.5);
+        let base: Arc<dyn Material> = match self.kind {
+            MaterialKind::Lambertian => Arc::new(Lambertian::new(albedo)),
+            MaterialKind::Metal => Arc::new(Metal::new(albedo, self.scalar_at("fuzz", time, 0.0))),
+            MaterialKind::Dielectric => {
+                let mut material = Dielectric::new(ir);
+                if let Some(ior) = self.dispersion {
+                    material.ior = ior;
+                }
+                Arc::new(material)
+            }
+            MaterialKind::Conductor(name) => {
+                let roughness_u = self.scalar_at("roughness-u", time, roughness);
+                let roughness_v = self.scalar_at("roughness-v", time, roughness);
+                let material = if roughness_u == roughness_v {
+                    Conductor::preset(name, roughness_u)
+                } else {
+                    conductor_preset(name)
+                        .map(|(eta, k)| Conductor::anisotropic(eta, k, roughness_u, roughness_v))
+                };
+                let mut material = material.expect("conductor presets are checked while parsing");
+                material.rotation = self.scalar_at("rotation", time, 0.0);
+                Arc::new(material)
+            }
+            MaterialKind::RoughDielectric => Arc::new(RoughDielectric::new(ir, roughness)),
+            MaterialKind::Principled => {
+                let base_color = match self.textures.iter().find(|(n, _)| *n == "albedo") {
+                    Some((_, texture)) => texture.clone(),
+                    None => Arc::new(SolidColor::new(albedo)),
+                };
+                let mut material = Principled::new(base_color);
+                material.metallic = self.texture_at("metallic", time, 0.0);
+                material.roughness = self.texture_at("roughness", time, 0.5);
+                material.specular = self.texture_at("specular", time, 0.5);
+                material.specular_tint = self.texture_at("specular-tint", time, 0.0);
+                material.sheen = self.texture_at("sheen", time, 0.0);
+                material.clearcoat = self.texture_at("clearcoat", time, 0.0);
+                material.transmission = self.texture_at("transmission", time, 0.0);
+                material.emission = match self.textures.iter().find(|(n, _)| *n == "emission") {
+                    Some((_, texture)) => texture.clone(),
+                    None => Arc::new(SolidColor::new(
+                        self.emission.at(time).unwrap_or(Vec3::new(0.0, 0.0, 0.0)),
+                    )),
+                };
+                material.ir = ir;
+                Arc::new(material)
+            }
+            MaterialKind::Subsurface => {
+                let mean_free_path = self
+                    .mean_free_path
+                    .at(time)
+                    .unwrap_or(Vec3::new(0.1, 0.1, 0.1));
+                let mut medium = Medium::new(mean_free_path, albedo);
+                medium.anisotropy = self.scalar_at("anisotropy", time, 0.0);
+                Arc::new(Subsurface::new(medium, ir))
+            }
+        };
+
+        let coating = match self.coating {
+            Some(CoatingKind::Clear) => Coating::Clear {
+                ir: self.scalar_at("coat-ir", time, 1.5),
+            },
+            Some(CoatingKind::Film) => Coating::Film {
+                thickness: self.scalar_at("film-thickness", time, 400.0),
+                ir: self.scalar_at("coat-ir", time, 1.33),
+                substrate_ir: self.scalar_at("substrate-ir", time, 1.0),
+            },
+            None => return self.cut_out(self.detailed(base, time), time),
+        };
+        let coated = Arc::new(Coated::new(base, coating));
+        return self.cut_out(self.detailed(coated, time), time);
+    }
+
+    // The material under its opacity mask, if it has one or a keyed opacity.
+    fn cut_out(&self, base: Arc<dyn Material>, time: f32) -> Arc<dyn Material> {
+        let masked = self.textures.iter().any(|(n, _)| *n == "opacity");
+        if !masked && self.scalar_at("opacity", time, 1.0) >= 1.0 {
+            return base;
+        }
+        return Arc::new(Cutout::new(base, self.texture_at("opacity", time, 1.0)));
+    }
+
+    // The material under its normal or bump map, if it has one.
+    fn detailed(&self, base: Arc<dyn Material>, time: f32) -> Arc<dyn Material> {
+        let texture = |name: &str| {
+            self.textures
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, texture)| texture.clone())
+        };
+        let detail = if let Some(height) = texture("bump") {
+            Detail::BumpMap {
+                height,
+                scale: self.scalar_at("bump-scale", time, 0.01),
+            }
+        } else if let Some(map) = texture("normal") {
+            Detail::NormalMap(map)
+        } else {
+            return base;
+        };
+        return Arc::new(Detailed::new(base, detail));
+    }
+}
+
+// Animation of one object of the scene. Negative indices count from the end of
+// the object list, so -1 is the last object added.
+#[derive(Clone)]
+struct ObjectTracks {
+    index: i64,
+    pivot: Vec3,
+    translate: Track<Vec3>,
+    rotate: Track<AxisAngle>,
+    material: Option<MaterialTracks>,
+}
+
+// Keyframes for the camera and the scene, read from a text file with one
+// statement per line:
+//
+//   fps 24
+//   frames 0 48
+//   interpolation linear|catmull-rom
+//   camera lookfrom|lookat <time> x y z
+//   camera vfov|aperture <time> value
+//   quad x y z ux uy uz vx vy vz
+//   object <index> pivot x y z
+//   object <index> translate <time> x y z
+//   object <index> rotate <time> axis_x axis_y axis_z degrees
+//   material <index> lambertian|metal|dielectric|rough-dielectric|principled
+//   material <index> subsurface
+//   material <index> conductor gold|copper|aluminium
+//   material <index> dispersion bk7|flint|diamond
+//   material <index> cauchy a b
+//   material <index> coating clear|film|none
+//   material <index> albedo|emission|mean-free-path <time> r g b
+//   material <index> <parameter> <time> value
+//   material <index> texture <parameter> FILE
+//
+// Scalar parameters are fuzz, ir, roughness, metallic, specular,
+// specular-tint, sheen, clearcoat, transmission, anisotropy, bump-scale,
+// opacity, roughness-u, roughness-v and rotation for brushed conductors, and
+// coat-ir, film-thickness and substrate-ir for coatings, which go over any
+// material. Textures apply to the principled material, but for the normal
+// and bump maps and opacity masks any material takes, and are found
+// relative to the animation file. Dispersion gives a dielectric a glass's
+// index, or Cauchy's a + b / lambda^2 with lambda in micrometers, and shows
+// in spectral mode. Quads are added to the scene after its own objects, as
+// gray lambertian parallelograms with a corner and two edges, for materials
+// to be put on. Rotation keys about the same axis turn through the whole
+// angle between them, so 0 and 360 degrees make a full turn, while keys about
+// different axes take the shorter way from one to the other. Times are in
+// seconds and # starts a comment.
+pub struct Animation {
+    pub fps: f32,
+    pub first_frame: u32,
+    pub last_frame: u32,
+    lookfrom: Track<Vec3>,
+    lookat: Track<Vec3>,
+    vfov: Track<f32>,
+    aperture: Track<f32>,
+    objects: Vec<ObjectTracks>,
+    quads: Vec<(Vec3, Vec3, Vec3)>,
+}
+
+impl Animation {
+    pub fn load(path: &str) -> Result<Animation, String> {
+        let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        return Animation::parse(path, &text);
+    }
+
+    // Source is the file the text came from, for finding textures.
+    pub fn parse(source: &str, text: &str) -> Result<Animation, String> {
+        let mut animation = Animation {
+            fps: 24.0,
+            first_frame: 0,
+            last_frame: 0,
+            lookfrom: Track::new(),
+            lookat: Track::new(),
+            vfov: Track::new(),
+            aperture: Track::new(),
+            objects: Vec::new(),
+            quads: Vec::new(),
+        };
+        let mut interpolation = Interpolation::Linear;
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.is_empty() {
+                continue;
+            }
+
+            animation
+                .parse_statement(source, &words, &mut interpolation)
+                .map_err(|err| format!("line {}: {}", number + 1, err))?;
+        }
+
+        if animation.last_frame < animation.first_frame {
+            return Err(String::from("the last frame comes before the first"));
+        }
+        if animation.fps <= 0.0 {
+            return Err(String::from("fps must be positive"));
+        }
+
+        animation.lookfrom.interpolation = interpolation;
+        animation.lookat.interpolation = interpolation;
+        animation.vfov.interpolation = interpolation;
+        animation.aperture.interpolation = interpolation;
+        for object in animation.objects.iter_mut() {
+            object.translate.interpolation = interpolation;
+            if let Some(material) = &mut object.material {
+                material.albedo.interpolation = interpolation;
+                material.emission.interpolation = interpolation;
+                material.mean_free_path.interpolation = interpolation;
+                for (_, track) in material.scalars.iter_mut() {
+                    track.interpolation = interpolation;
+                }
+            }
+        }
+
+        return Ok(animation);
+    }
+
+    fn parse_statement(
+        &mut self,
+        source: &str,
+        words: &[&str],
+        interpolation: &mut Interpolation,
+    ) -> Result<(), String> {
+        match words {
+            ["fps", fps] => self.fps = parse_f32(fps)?,
+            ["frames", first, last] => {
+                self.first_frame = parse_u32(first)?;
+                self.last_frame = parse_u32(last)?;
+            }
+            ["interpolation", name] => {
+                *interpolation = Interpolation::from_name(name)
+                    .ok_or_else(|| format!("unknown interpolation {}", name))?;
+            }
+            ["camera", "lookfrom", time, x, y, z] => {
+                self.lookfrom.add(parse_f32(time)?, parse_vec3(x, y, z)?);
+            }
+            ["camera", "lookat", time, x, y, z] => {
+                self.lookat.add(parse_f32(time)?, parse_vec3(x, y, z)?);
+            }
+            ["camera", "vfov", time, value] => {
+                self.vfov.add(parse_f32(time)?, parse_f32(value)?);
+            }
+            ["camera", "aperture", time, value] => {
+                self.aperture.add(parse_f32(time)?, parse_f32(value)?);
+            }
+            ["quad", x, y, z, ux, uy, uz, vx, vy, vz] => {
+                let q = parse_vec3(x, y, z)?;
+                let u = parse_vec3(ux, uy, uz)?;
+                let v = parse_vec3(vx, vy, vz)?;
+                if cross(u, v).near_zero() {
+                    return Err(String::from("the quad's edges are parallel"));
+                }
+                self.quads.push((q, u, v));
+            }
+            ["object", index, "pivot", x, y, z] => {
+                self.object(parse_index(index)?).pivot = parse_vec3(x, y, z)?;
+            }
+            ["object", index, "translate", time, x, y, z] => {
+                let key = (parse_f32(time)?, parse_vec3(x, y, z)?);
+                self.object(parse_index(index)?).translate.add(key.0, key.1);
+            }
+            ["object", index, "rotate", time, x, y, z, degrees] => {
+                let axis = parse_vec3(x, y, z)?;
+                if axis.near_zero() {
+                    return Err(String::from("the rotation axis is zero"));
+                }
+                let key = AxisAngle::new(axis, parse_f32(degrees)?);
+                let time = parse_f32(time)?;
+                self.object(parse_index(index)?).rotate.add(time, key);
+            }
+            ["material", index, "conductor", metal] => {
+                let kind = match *metal {
+                    "gold" => MaterialKind::Conductor("gold"),
+                    "copper" => MaterialKind::Conductor("copper"),
+                    "aluminium" => MaterialKind::Conductor("aluminium"),
+                    _ => return Err(format!("unknown conductor {}", metal)),
+                };
+                self.set_material_kind(parse_index(index)?, kind);
+            }
+            ["material", index, "dispersion", glass] => {
+                let ior = Ior::preset(glass).ok_or_else(|| format!("unknown glass {}", glass))?;
+                self.material(parse_index(index)?)?.dispersion = Some(ior);
+            }
+            ["material", index, "cauchy", a, b] => {
+                let ior = Ior::Cauchy {
+                    a: parse_f32(a)?,
+                    b: parse_f32(b)?,
+                };
+                self.material(parse_index(index)?)?.dispersion = Some(ior);
+            }
+            ["material", index, "coating", coating] => {
+                let coating = match *coating {
+                    "clear" => Some(CoatingKind::Clear),
+                    "film" => Some(CoatingKind::Film),
+                    "none" => None,
+                    _ => return Err(format!("unknown coating {}", coating)),
+                };
+                self.material(parse_index(index)?)?.coating = coating;
+            }
+            ["material", index, kind] => {
+                let kind = match *kind {
+                    "lambertian" => MaterialKind::Lambertian,
+                    "metal" => MaterialKind::Metal,
+                    "dielectric" => MaterialKind::Dielectric,
+                    "rough-dielectric" => MaterialKind::RoughDielectric,
+                    "principled" => MaterialKind::Principled,
+                    "subsurface" => MaterialKind::Subsurface,
+                    _ => return Err(format!("unknown material {}", kind)),
+                };
+                self.set_material_kind(parse_index(index)?, kind);
+            }
+            ["material", index, "albedo", time, r, g, b] => {
+                let key = (parse_f32(time)?, parse_vec3(r, g, b)?);
+                self.material(parse_index(index)?)?.albedo.add(key.0, key.1);
+            }
+            ["material", index, "mean-free-path", time, r, g, b] => {
+                let key = (parse_f32(time)?, parse_vec3(r, g, b)?);
+                self.material(parse_index(index)?)?
+                    .mean_free_path
+                    .add(key.0, key.1);
+            }
+            ["material", index, "emission", time, r, g, b] => {
+                let key = (parse_f32(time)?, parse_vec3(r, g, b)?);
+                self.material(parse_index(index)?)?
+                    .emission
+                    .add(key.0, key.1);
+            }
+            ["material", index, "texture", parameter, file] => {
+                let parameter = match TEXTURE_PARAMETERS.iter().find(|p| *p == parameter) {
+                    Some(parameter) => *parameter,
+                    None => return Err(format!("{} can't be textured", parameter)),
+                };
+                let file = Path::new(source).with_file_name(file);
+                let file = file.to_string_lossy();
+                // Colors are stored in sRGB, the other parameters as they are.
+                // Opacity can come from an alpha channel.
+                let srgb = parameter == "albedo" || parameter == "emission";
+                let texture = if parameter == "opacity" {
+                    ImageTexture::load_opacity(&file)
+                } else {
+                    ImageTexture::load(&file, srgb)
+                };
+                let texture = texture.map_err(|err| format!("{}: {}", file, err))?;
+
+                let material = self.material(parse_index(index)?)?;
+                material.textures.retain(|(n, _)| *n != parameter);
+                material.textures.push((parameter, Arc::new(texture)));
+            }
+            ["material", index, parameter, time, value] => {
+                let key = (parse_f32(time)?, parse_f32(value)?);
+                let material = self.material(parse_index(index)?)?;
+                match material.scalar(parameter) {
+                    Some(track) => track.add(key.0, key.1),
+                    None => return Err(format!("unknown material parameter {}", parameter)),
+                }
+            }
+            _ => return Err(format!("unknown statement '{}'", words.join(" "))),
+        }
+        return Ok(());
+    }
+
+    fn object(&mut self, index: i64) -> &mut ObjectTracks {
+        if let Some(i) = self.objects.iter().position(|o| o.index == index) {
+            return &mut self.objects[i];
+        }
+        self.objects.push(ObjectTracks {
+            index,
+            pivot: Vec3::new(0.0, 0.0, 0.0),
+            translate: Track::new(),
+            rotate: Track::new(),
+            material: None,
+        });
+        return self.objects.last_mut().unwrap();
+    }
+
+    fn set_material_kind(&mut self, index: i64, kind: MaterialKind) {
+        let object = self.object(index);
+        match &mut object.material {
+            Some(material) => material.kind = kind,
+            None => object.material = Some(MaterialTracks::new(kind)),
+        }
+    }
+
+    fn material(&mut self, index: i64) -> Result<&mut MaterialTracks, String> {
+        return self
+            .object(index)
+            .material
+            .as_mut()
+            .ok_or_else(|| format!("give material {} a kind before its keys", index));
+    }
+
+    pub fn frame_time(&self, frame: u32) -> f32 {
+        return frame as f32 / self.fps;
+    }
+
+    pub fn apply_camera(&self, controller: &mut CameraController, time: f32) {
+        if let Some(lookfrom) = self.lookfrom.at(time) {
+            controller.lookfrom = lookfrom;
+        }
+        if let Some(lookat) = self.lookat.at(time) {
+            controller.lookat = lookat;
+        }
+        if let Some(vfov) = self.vfov.at(time) {
+            controller.vfov = vfov;
+        }
+        if let Some(aperture) = self.aperture.at(time) {
+            controller.aperture = aperture;
+        }
+    }
+
+    // Adds the quads to the world and wraps its animated objects. Their
+    // transforms follow each ray's time, their materials are fixed at the
+    // given frame time.
+    pub fn animate_world(&self, world: HittableList, time: f32) -> Result<HittableList, String> {
+        let mut objects: Vec<Option<Box<dyn Hittable>>> =
+            world.into_objects().into_iter().map(Some).collect();
+        for (q, u, v) in &self.quads {
+            let material = Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+            objects.push(Some(Box::new(Quad::new(*q, *u, *v, material))));
+        }
+        let count = objects.len() as i64;
+
+        for tracks in &self.objects {
+            let i = if tracks.index < 0 {
+                count + tracks.index
+            } else {
+                tracks.index
+            };
+            if i < 0 || i >= count {
+                return Err(format!(
+                    "object {} is out of range, the scene has {} objects",
+                    tracks.index, count
+                ));
+            }
+
+            let object = match objects[i as usize].take() {
+                Some(object) => object,
+                None => return Err(format!("object {} is animated twice", tracks.index)),
+            };
+            objects[i as usize] = Some(Box::new(Keyframed::new(
+                object,
+                tracks.pivot,
+                tracks.translate.clone(),
+                tracks.rotate.clone(),
+                tracks.material.as_ref().map(|m| m.build(time)),
+            )));
+        }
+
+        let mut animated = HittableList::new();
+        for object in objects.into_iter().flatten() {
+            animated.add(object);
+        }
+        return Ok(animated);
+    }
+}
+
+fn parse_f32(text: &str) -> Result<f32, String> {
+    return text
+        .parse::<f32>()
+        .map_err(|err| format!("invalid number {}: {}", text, err));
+}
+
+fn parse_u32(text: &str) -> Result<u32, String> {
+    return text
+        .parse::<u32>()
+        .map_err(|err| format!("invalid frame {}: {}", text, err));
+}
+
+fn parse_index(text: &str) -> Result<i64, String> {
+    return text
+        .parse::<i64>()
+        .map_err(|err| format!("invalid index {}: {}", text, err));
+}
+
+fn parse_vec3(x: &str, y: &str, z: &str) -> Result<Vec3, String> {
+    return Ok(Vec3::new(parse_f32(x)?, parse_f32(y)?, parse_f32(z)?));
+}
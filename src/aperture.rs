@@ -0,0 +1,103 @@
+use crate::consts::*;
+use crate::math::vec3::Vec3;
+
+use std::sync::Arc;
+
+// Shape of the lens opening, which is also the shape of out of focus
+// highlights. Samples are points in the xy plane within [-1, 1]^2, scaled by
+// the lens radius in the camera.
+#[derive(Clone)]
+pub enum ApertureShape {
+    Circle,
+    Polygon { blades: u32, rotation: f32 },
+    Mask(Arc<ApertureMask>),
+}
+
+impl ApertureShape {
+    pub fn sample(&self, u: f32, v: f32) -> Vec3 {
+        return match self {
+            ApertureShape::Circle => Vec3::concentric_disk(u, v),
+            ApertureShape::Polygon { blades, rotation } => sample_polygon(*blades, *rotation, u, v),
+            ApertureShape::Mask(mask) => mask.sample(u, v),
+        };
+    }
+
+    pub fn describe(&self) -> String {
+        return match self {
+            ApertureShape::Circle => String::from("circle"),
+            ApertureShape::Polygon { blades, rotation } => {
+                format!("polygon {} {}", blades, rotation)
+            }
+            ApertureShape::Mask(mask) => format!("mask {}", mask.source),
+        };
+    }
+}
+
+// Regular polygon inscribed in the unit circle: u picks the triangle between
+// the center and two neighbouring blade corners and is then reused within it.
+fn sample_polygon(blades: u32, rotation: f32, u: f32, v: f32) -> Vec3 {
+    let n = blades.max(3) as f32;
+    let scaled = u * n;
+    let i = f32::min(f32::floor(scaled), n - 1.0);
+    let u = scaled - i;
+
+    let a0 = rotation + 2.0 * PI * i / n;
+    let a1 = rotation + 2.0 * PI * (i + 1.0) / n;
+    let b = Vec3::new(f32::cos(a0), f32::sin(a0), 0.0);
+    let c = Vec3::new(f32::cos(a1), f32::sin(a1), 0.0);
+
+    let su = f32::sqrt(u);
+    return su * (1.0 - v) * b + su * v * c;
+}
+
+// Grayscale image whose brightness is the transmission of the aperture.
+pub struct ApertureMask {
+    pub source: String,
+    width: usize,
+    height: usize,
+    cdf: Vec<f32>,
+}
+
+impl ApertureMask {
+    // values are row major with row 0 at the top, like the image they came from.
+    pub fn new(source: &str, width: usize, height: usize, values: &[f32]) -> Option<Self> {
+        let mut cdf = Vec::with_capacity(values.len());
+        let mut total = 0.0;
+        for value in values {
+            total += f32::max(*value, 0.0);
+            cdf.push(total);
+        }
+        if total <= 0.0 {
+            return None;
+        }
+        for c in cdf.iter_mut() {
+            *c /= total;
+        }
+
+        return Some(Self {
+            source: String::from(source),
+            width,
+            height,
+            cdf,
+        });
+    }
+
+    // Picks a pixel proportionally to its transmission, reusing what is left of
+    // u after the pick to jitter inside it.
+    fn sample(&self, u: f32, v: f32) -> Vec3 {
+        let i = self
+            .cdf
+            .partition_point(|c| *c <= u)
+            .min(self.cdf.len() - 1);
+        let low = if i == 0 { 0.0 } else { self.cdf[i - 1] };
+        let du = clamp((u - low) / f32::max(self.cdf[i] - low, 1e-8), 0.0, 1.0);
+
+        let x = (i % self.width) as f32 + du;
+        let y = (i / self.width) as f32 + v;
+        return Vec3::new(
+            2.0 * x / self.width as f32 - 1.0,
+            1.0 - 2.0 * y / self.height as f32,
+            0.0,
+        );
+    }
+}
@@ -1,16 +1,126 @@
-use crate::consts::degrees_to_radians;
+use crate::aperture::ApertureShape;
+use crate::consts::*;
 use crate::math::vec3::*;
 use crate::ray::Ray;
 
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Projection {
+    Perspective,
+    // Parallel rays; the view covers what the perspective view shows at focus_dist.
+    Orthographic,
+    // vfov is the angle covered by the image circle, which fits the image height.
+    FisheyeEquidistant,
+    FisheyeEquisolid,
+    // Full 360 x 180 degree panorama, best rendered at a 2:1 aspect ratio.
+    Equirectangular,
+}
+
+impl Projection {
+    pub fn from_name(name: &str) -> Option<Projection> {
+        return match name {
+            "perspective" => Some(Projection::Perspective),
+            "orthographic" => Some(Projection::Orthographic),
+            "fisheye-equidistant" => Some(Projection::FisheyeEquidistant),
+            "fisheye-equisolid" => Some(Projection::FisheyeEquisolid),
+            "equirectangular" => Some(Projection::Equirectangular),
+            _ => None,
+        };
+    }
+
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Projection::Perspective => "perspective",
+            Projection::Orthographic => "orthographic",
+            Projection::FisheyeEquidistant => "fisheye-equidistant",
+            Projection::FisheyeEquisolid => "fisheye-equisolid",
+            Projection::Equirectangular => "equirectangular",
+        };
+    }
+}
+
+// Stereo pairs put the left eye in the left or top half of the image. Each
+// eye is offset sideways from the ray direction, so 360 degree panoramas
+// become omni-directional stereo.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Stereo {
+    Mono,
+    SideBySide { interocular: f32 },
+    TopBottom { interocular: f32 },
+}
+
+impl Stereo {
+    pub fn from_name(name: &str, interocular: f32) -> Option<Stereo> {
+        return match name {
+            "mono" => Some(Stereo::Mono),
+            "side-by-side" => Some(Stereo::SideBySide { interocular }),
+            "top-bottom" => Some(Stereo::TopBottom { interocular }),
+            _ => None,
+        };
+    }
+
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Stereo::Mono => "mono",
+            Stereo::SideBySide { .. } => "side-by-side",
+            Stereo::TopBottom { .. } => "top-bottom",
+        };
+    }
+
+    pub fn interocular(&self) -> f32 {
+        return match self {
+            Stereo::Mono => 0.0,
+            Stereo::SideBySide { interocular } | Stereo::TopBottom { interocular } => *interocular,
+        };
+    }
+
+    // Maps image coordinates to the eye (-1 left, 1 right, 0 mono) and the
+    // coordinates within that eye's half of the image.
+    fn split(&self, s: f32, t: f32) -> (f32, f32, f32) {
+        return match self {
+            Stereo::Mono => (0.0, s, t),
+            Stereo::SideBySide { .. } => {
+                if s < 0.5 {
+                    (-1.0, 2.0 * s, t)
+                } else {
+                    (1.0, 2.0 * s - 1.0, t)
+                }
+            }
+            // t grows upwards, so the top half is the left eye.
+            Stereo::TopBottom { .. } => {
+                if t >= 0.5 {
+                    (-1.0, s, 2.0 * t - 1.0)
+                } else {
+                    (1.0, s, 2.0 * t)
+                }
+            }
+        };
+    }
+}
+
 pub struct Camera {
     pub origin: Vec3,
     pub lower_left_corner: Vec3,
     pub horizontal: Vec3,
     pub vertical: Vec3,
+    pub time0: f32,
+    pub time1: f32,
+    pub projection: Projection,
+    pub stereo: Stereo,
+    pub aperture_shape: ApertureShape,
+    // How strongly the lens barrel clips the aperture off axis, giving cat's
+    // eye shaped bokeh towards the image corners. 0 disables it.
+    pub cat_eye: f32,
+    // Rotation of the focal plane in degrees, around the horizontal (tilt)
+    // and vertical (swing) image axes.
+    pub tilt: f32,
+    pub swing: f32,
     u: Vec3,
     v: Vec3,
     w: Vec3,
     lens_radius: f32,
+    focus_dist: f32,
+    vfov: f32,
+    aspect_ratio: f32,
 }
 
 impl Camera {
@@ -43,21 +153,140 @@ impl Camera {
             origin,
             horizontal,
             vertical,
+            time0: 0.0,
+            time1: 0.0,
+            projection: Projection::Perspective,
+            stereo: Stereo::Mono,
+            aperture_shape: ApertureShape::Circle,
+            cat_eye: 0.0,
+            tilt: 0.0,
+            swing: 0.0,
             lower_left_corner,
             u,
             v,
             w,
             lens_radius,
+            focus_dist,
+            vfov,
+            aspect_ratio,
         }
     }
 
-    pub fn get_ray(&self, s: f32, t: f32) -> Ray {
-        let rd = self.lens_radius * Vec3::random_in_unit_disk();
+    pub fn get_ray(&self, s: f32, t: f32) -> Option<Ray> {
+        return self.get_ray_sampled(s, t, (random(), random()), random());
+    }
+
+    // Like get_ray, with the lens position and shutter time taken from the
+    // given samples in [0, 1). Returns None where the projection doesn't cover
+    // the image, like the corners outside a fisheye circle, and for lens
+    // samples blocked by cat's eye vignetting.
+    pub fn get_ray_sampled(&self, s: f32, t: f32, lens: (f32, f32), time: f32) -> Option<Ray> {
+        let (eye, s, t) = self.stereo.split(s, t);
+
+        // Stereo halves keep the vertical field of view and narrow or widen
+        // the horizontal one.
+        let horizontal = self.horizontal * (self.eye_aspect_ratio() / self.aspect_ratio);
+
+        // Where the ray starts and a point it passes through in focus.
+        let (mut origin, mut focus_point) = match self.projection {
+            Projection::Perspective => (
+                self.origin,
+                self.lower_left_corner
+                    + (self.horizontal - horizontal) / 2.0
+                    + horizontal * s
+                    + self.vertical * t,
+            ),
+            Projection::Orthographic => {
+                let origin = self.origin + (s - 0.5) * horizontal + (t - 0.5) * self.vertical;
+                (origin, origin - self.focus_dist * self.w)
+            }
+            Projection::FisheyeEquidistant | Projection::FisheyeEquisolid => {
+                let x = (2.0 * s - 1.0) * self.eye_aspect_ratio();
+                let y = 2.0 * t - 1.0;
+                let r = f32::sqrt(x * x + y * y);
+                if r > 1.0 {
+                    return None;
+                }
+
+                let half_fov = degrees_to_radians(self.vfov) / 2.0;
+                let theta = if self.projection == Projection::FisheyeEquidistant {
+                    r * half_fov
+                } else {
+                    2.0 * f32::asin(clamp(r * f32::sin(half_fov / 2.0), -1.0, 1.0))
+                };
+                let phi = f32::atan2(y, x);
+
+                let direction = f32::sin(theta) * f32::cos(phi) * self.u
+                    + f32::sin(theta) * f32::sin(phi) * self.v
+                    - f32::cos(theta) * self.w;
+                (self.origin, self.origin + self.focus_dist * direction)
+            }
+            Projection::Equirectangular => {
+                let longitude = (s - 0.5) * 2.0 * PI;
+                let latitude = (t - 0.5) * PI;
+
+                let direction = f32::cos(latitude) * f32::sin(longitude) * self.u
+                    + f32::sin(latitude) * self.v
+                    - f32::cos(latitude) * f32::cos(longitude) * self.w;
+                (self.origin, self.origin + self.focus_dist * direction)
+            }
+        };
+
+        // A tilted focal plane still passes through the focus point on the
+        // optical axis; move the focus point along the pinhole ray onto it.
+        if self.tilt != 0.0 || self.swing != 0.0 {
+            let tilt = degrees_to_radians(self.tilt);
+            let swing = degrees_to_radians(self.swing);
+            let normal = unit_vector(
+                f32::cos(tilt) * f32::cos(swing) * self.w
+                    + f32::sin(tilt) * self.v
+                    + f32::sin(swing) * self.u,
+            );
+            let plane_point = self.origin - self.focus_dist * self.w;
+            let direction = focus_point - origin;
+            let denominator = dot(direction, normal);
+            if f32::abs(denominator) > 1e-6 {
+                let k = dot(plane_point - origin, normal) / denominator;
+                if k > 0.0 {
+                    focus_point = origin + k * direction;
+                }
+            }
+        }
+
+        if eye != 0.0 {
+            let side = cross(focus_point - origin, self.v);
+            if !side.near_zero() {
+                let offset = eye * self.stereo.interocular() / 2.0 * unit_vector(side);
+                origin = origin + offset;
+            }
+        }
+
+        let lens_point = self.aperture_shape.sample(lens.0, lens.1);
+
+        // The barrel is modelled as a second unit circle that slides across the
+        // aperture as the pixel moves away from the image center.
+        if self.cat_eye > 0.0 {
+            let from_center = Vec3::new(2.0 * s - 1.0, 2.0 * t - 1.0, 0.0);
+            if (lens_point - self.cat_eye * from_center).length_squared() > 1.0 {
+                return None;
+            }
+        }
+
+        let rd = self.lens_radius * lens_point;
         let offset = self.u * rd.x + self.v * rd.y;
 
-        Ray::new(
-            self.origin + offset,
-            self.lower_left_corner + self.horizontal * s + self.vertical * t - self.origin - offset,
-        )
+        return Some(Ray::with_time(
+            origin + offset,
+            focus_point - origin - offset,
+            self.time0 + time * (self.time1 - self.time0),
+        ));
+    }
+
+    fn eye_aspect_ratio(&self) -> f32 {
+        return match self.stereo {
+            Stereo::Mono => self.aspect_ratio,
+            Stereo::SideBySide { .. } => self.aspect_ratio / 2.0,
+            Stereo::TopBottom { .. } => self.aspect_ratio * 2.0,
+        };
     }
 }
@@ -0,0 +1,209 @@
+use crate::aperture::ApertureShape;
+use crate::camera::{Camera, Projection, Stereo};
+use crate::consts::*;
+use crate::math::vec3::*;
+
+use raylib::consts::KeyboardKey::*;
+use raylib::consts::MouseButton::*;
+use raylib::prelude::*;
+
+const MOVE_SPEED: f32 = 4.0;
+const ORBIT_SPEED: f32 = 0.005;
+const ZOOM_SPEED: f32 = 2.0;
+const APERTURE_SPEED: f32 = 0.25;
+const FOCUS_SPEED: f32 = 4.0;
+
+pub struct CameraController {
+    pub lookfrom: Vec3,
+    pub lookat: Vec3,
+    pub vup: Vec3,
+    pub vfov: f32,
+    pub aspect_ratio: f32,
+    pub aperture: f32,
+    pub focus_dist: f32,
+    pub projection: Projection,
+    pub stereo: Stereo,
+    pub aperture_shape: ApertureShape,
+    pub cat_eye: f32,
+    pub tilt: f32,
+    pub swing: f32,
+    last_mouse: Option<Vector2>,
+}
+
+impl CameraController {
+    pub fn new(
+        lookfrom: Vec3,
+        lookat: Vec3,
+        vup: Vec3,
+        vfov: f32,
+        aspect_ratio: f32,
+        aperture: f32,
+        focus_dist: f32,
+    ) -> Self {
+        Self {
+            lookfrom,
+            lookat,
+            vup,
+            vfov,
+            aspect_ratio,
+            aperture,
+            focus_dist,
+            projection: Projection::Perspective,
+            stereo: Stereo::Mono,
+            aperture_shape: ApertureShape::Circle,
+            cat_eye: 0.0,
+            tilt: 0.0,
+            swing: 0.0,
+            last_mouse: None,
+        }
+    }
+
+    pub fn camera(&self) -> Camera {
+        let mut camera = Camera::new(
+            self.lookfrom,
+            self.lookat,
+            self.vup,
+            self.vfov,
+            self.aspect_ratio,
+            self.aperture,
+            self.focus_dist,
+        );
+        camera.projection = self.projection;
+        camera.stereo = self.stereo;
+        camera.aperture_shape = self.aperture_shape.clone();
+        camera.cat_eye = self.cat_eye;
+        camera.tilt = self.tilt;
+        camera.swing = self.swing;
+        return camera;
+    }
+
+    // Applies this frame's input and returns true when the view changed.
+    pub fn update(&mut self, rl: &RaylibHandle) -> bool {
+        let dt = rl.get_frame_time();
+        let mut changed = false;
+
+        // WASD flies the eye and target together, Q/E move along vup.
+        let forward = unit_vector(self.lookat - self.lookfrom);
+        let right = unit_vector(cross(forward, self.vup));
+        let mut movement = Vec3::new(0.0, 0.0, 0.0);
+
+        if rl.is_key_down(KEY_W) {
+            movement = movement + forward;
+        }
+        if rl.is_key_down(KEY_S) {
+            movement = movement - forward;
+        }
+        if rl.is_key_down(KEY_D) {
+            movement = movement + right;
+        }
+        if rl.is_key_down(KEY_A) {
+            movement = movement - right;
+        }
+        if rl.is_key_down(KEY_E) {
+            movement = movement + self.vup;
+        }
+        if rl.is_key_down(KEY_Q) {
+            movement = movement - self.vup;
+        }
+
+        if !movement.near_zero() {
+            let offset = MOVE_SPEED * dt * unit_vector(movement);
+            self.lookfrom = self.lookfrom + offset;
+            self.lookat = self.lookat + offset;
+            changed = true;
+        }
+
+        // Dragging with the left button orbits lookfrom around lookat.
+        if rl.is_mouse_button_down(MOUSE_LEFT_BUTTON) {
+            let mouse = rl.get_mouse_position();
+            if let Some(last) = self.last_mouse {
+                let dx = mouse.x - last.x;
+                let dy = mouse.y - last.y;
+                if dx != 0.0 || dy != 0.0 {
+                    self.orbit(-dx * ORBIT_SPEED, dy * ORBIT_SPEED);
+                    changed = true;
+                }
+            }
+            self.last_mouse = Some(mouse);
+        } else {
+            self.last_mouse = None;
+        }
+
+        let wheel = rl.get_mouse_wheel_move();
+        if wheel != 0.0 {
+            self.vfov = clamp(self.vfov - wheel * ZOOM_SPEED, 1.0, 170.0);
+            changed = true;
+        }
+
+        if rl.is_key_down(KEY_RIGHT_BRACKET) {
+            self.aperture += APERTURE_SPEED * dt;
+            changed = true;
+        }
+        if rl.is_key_down(KEY_LEFT_BRACKET) {
+            self.aperture = f32::max(self.aperture - APERTURE_SPEED * dt, 0.0);
+            changed = true;
+        }
+        if rl.is_key_down(KEY_EQUAL) {
+            self.focus_dist += FOCUS_SPEED * dt;
+            changed = true;
+        }
+        if rl.is_key_down(KEY_MINUS) {
+            self.focus_dist = f32::max(self.focus_dist - FOCUS_SPEED * dt, 0.1);
+            changed = true;
+        }
+
+        if rl.is_key_pressed(KEY_P) {
+            println!("{}", self.to_scene_string());
+        }
+
+        return changed;
+    }
+
+    // Moves the focal plane through point, measured along the view direction.
+    pub fn focus_on(&mut self, point: Vec3) {
+        let forward = unit_vector(self.lookat - self.lookfrom);
+        self.focus_dist = f32::max(dot(point - self.lookfrom, forward), 0.1);
+    }
+
+    fn orbit(&mut self, yaw: f32, pitch: f32) {
+        let offset = self.lookfrom - self.lookat;
+        let radius = offset.length();
+        let limit = degrees_to_radians(89.0);
+
+        let theta = f32::atan2(offset.x, offset.z) + yaw;
+        let phi = clamp(f32::asin(offset.y / radius) + pitch, -limit, limit);
+
+        self.lookfrom = self.lookat
+            + radius
+                * Vec3::new(
+                    f32::cos(phi) * f32::sin(theta),
+                    f32::sin(phi),
+                    f32::cos(phi) * f32::cos(theta),
+                );
+    }
+
+    pub fn to_scene_string(&self) -> String {
+        return format!(
+            "camera {{\n    lookfrom {} {} {}\n    lookat {} {} {}\n    vup {} {} {}\n    vfov {}\n    aperture {}\n    focus_dist {}\n    projection {}\n    stereo {} {}\n    aperture_shape {}\n    cat_eye {}\n    tilt {}\n    swing {}\n}}",
+            self.lookfrom.x,
+            self.lookfrom.y,
+            self.lookfrom.z,
+            self.lookat.x,
+            self.lookat.y,
+            self.lookat.z,
+            self.vup.x,
+            self.vup.y,
+            self.vup.z,
+            self.vfov,
+            self.aperture,
+            self.focus_dist,
+            self.projection.name(),
+            self.stereo.name(),
+            self.stereo.interocular(),
+            self.aperture_shape.describe(),
+            self.cat_eye,
+            self.tilt,
+            self.swing,
+        );
+    }
+}
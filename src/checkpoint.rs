@@ -0,0 +1,149 @@
+use crate::film::Film;
+use crate::math::vec3::Vec3;
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+const MAGIC: &[u8; 4] = b"RTCK";
+const VERSION: u32 = 4;
+
+pub struct Checkpoint {
+    pub seed: u64,
+    pub pass: u32,
+    pub scene_hash: u64,
+    pub settings_hash: u64,
+}
+
+// FNV-1a, used instead of DefaultHasher because the hashes are written to disk
+// and must not change between compiler versions.
+pub fn hash(text: &str) -> u64 {
+    let mut h: u64 = 0xcbf29ce484222325;
+    for byte in text.bytes() {
+        h ^= byte as u64;
+        h = h.wrapping_mul(0x100000001b3);
+    }
+    return h;
+}
+
+// Seed for the random sequence of a given pass, so a resumed render continues
+// exactly where the checkpoint stopped.
+pub fn pass_seed(seed: u64, pass: u32) -> u64 {
+    let mut z = seed.wrapping_add((pass as u64 + 1).wrapping_mul(0x9e3779b97f4a7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    return z ^ (z >> 31);
+}
+
+pub fn save(
+    path: &str,
+    seed: u64,
+    pass: u32,
+    scene_hash: u64,
+    settings_hash: u64,
+    film: &Film,
+) -> io::Result<()> {
+    // Write next to the old checkpoint first so a crash mid-write keeps it intact.
+    let tmp_path = format!("{}.tmp", path);
+    {
+        let mut out = BufWriter::new(File::create(&tmp_path)?);
+
+        out.write_all(MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+        out.write_all(&seed.to_le_bytes())?;
+        out.write_all(&pass.to_le_bytes())?;
+        out.write_all(&scene_hash.to_le_bytes())?;
+        out.write_all(&settings_hash.to_le_bytes())?;
+        out.write_all(&(film.width as u32).to_le_bytes())?;
+        out.write_all(&(film.height as u32).to_le_bytes())?;
+
+        for i in 0..film.sum.len() {
+            write_vec3(&mut out, film.sum[i])?;
+            out.write_all(&film.weight[i].to_le_bytes())?;
+            out.write_all(&film.samples[i].to_le_bytes())?;
+            out.write_all(&film.lum_sum[i].to_le_bytes())?;
+            out.write_all(&film.lum_sum_sq[i].to_le_bytes())?;
+            write_vec3(&mut out, film.albedo_sum[i])?;
+            write_vec3(&mut out, film.normal_sum[i])?;
+        }
+
+        out.flush()?;
+    }
+
+    return fs::rename(&tmp_path, path);
+}
+
+// Reads a checkpoint, restoring the accumulated pixels into film.
+pub fn load(path: &str, film: &mut Film) -> io::Result<Checkpoint> {
+    let mut input = BufReader::new(File::open(path)?);
+
+    let mut magic = [0u8; 4];
+    input.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("not a render checkpoint"));
+    }
+    if read_u32(&mut input)? != VERSION {
+        return Err(invalid("unsupported checkpoint version"));
+    }
+
+    let seed = read_u64(&mut input)?;
+    let pass = read_u32(&mut input)?;
+    let scene_hash = read_u64(&mut input)?;
+    let settings_hash = read_u64(&mut input)?;
+    let width = read_u32(&mut input)? as usize;
+    let height = read_u32(&mut input)? as usize;
+    if width != film.width || height != film.height {
+        return Err(invalid("the checkpoint resolution differs from the render"));
+    }
+
+    for i in 0..width * height {
+        film.sum[i] = read_vec3(&mut input)?;
+        film.weight[i] = read_f32(&mut input)?;
+        film.samples[i] = read_u32(&mut input)?;
+        film.lum_sum[i] = read_f32(&mut input)?;
+        film.lum_sum_sq[i] = read_f32(&mut input)?;
+        film.albedo_sum[i] = read_vec3(&mut input)?;
+        film.normal_sum[i] = read_vec3(&mut input)?;
+    }
+
+    return Ok(Checkpoint {
+        seed,
+        pass,
+        scene_hash,
+        settings_hash,
+    });
+}
+
+fn invalid(message: &str) -> io::Error {
+    return io::Error::new(io::ErrorKind::InvalidData, message);
+}
+
+fn read_u32(input: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    input.read_exact(&mut bytes)?;
+    return Ok(u32::from_le_bytes(bytes));
+}
+
+fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    input.read_exact(&mut bytes)?;
+    return Ok(u64::from_le_bytes(bytes));
+}
+
+fn read_f32(input: &mut impl Read) -> io::Result<f32> {
+    let mut bytes = [0u8; 4];
+    input.read_exact(&mut bytes)?;
+    return Ok(f32::from_le_bytes(bytes));
+}
+
+fn read_vec3(input: &mut impl Read) -> io::Result<Vec3> {
+    let x = read_f32(input)?;
+    let y = read_f32(input)?;
+    let z = read_f32(input)?;
+    return Ok(Vec3::new(x, y, z));
+}
+
+fn write_vec3(out: &mut impl Write, v: Vec3) -> io::Result<()> {
+    out.write_all(&v.x.to_le_bytes())?;
+    out.write_all(&v.y.to_le_bytes())?;
+    return out.write_all(&v.z.to_le_bytes());
+}
@@ -16,3 +16,7 @@ pub fn write_color(pixel_color: &mut Vec3, samples_per_pixel: i32) {
     pixel_color.y = 256.0 * clamp(g, 0.0, 0.999);
     pixel_color.z = 256.0 * clamp(b, 0.0, 0.999);
 }
+
+pub fn luminance(color: Vec3) -> f32 {
+    return 0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z;
+}
@@ -1,4 +1,6 @@
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cell::RefCell;
 
 pub const INFINITY: f32 = f32::INFINITY;
 pub const PI: f32 = 3.1415926535897932385;
@@ -7,9 +9,17 @@ pub fn degrees_to_radians(degrees: f32) -> f32 {
     return degrees * PI / 180.0;
 }
 
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+// Restarts this thread's random sequence so renders can be reproduced.
+pub fn seed_random(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
 pub fn random() -> f32 {
-    let mut rng = rand::thread_rng();
-    return rng.gen::<f32>();
+    return RNG.with(|rng| rng.borrow_mut().gen::<f32>());
 }
 
 pub fn random_range(min: f32, max: f32) -> f32 {
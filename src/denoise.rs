@@ -0,0 +1,94 @@
+use crate::color::luminance;
+use crate::math::vec3::*;
+
+const ITERATIONS: usize = 5;
+const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+const SIGMA_COLOR: f32 = 1.0;
+const SIGMA_NORMAL: f32 = 0.3;
+const SIGMA_ALBEDO: f32 = 0.1;
+
+// Edge-avoiding à-trous wavelet filter (Dammertz et al. 2010, "Edge-Avoiding
+// À-Trous Wavelet Transform for fast Global Illumination Filtering").
+// The albedo is divided out before filtering and multiplied back afterwards so
+// only the lighting is blurred, while the normal and albedo buffers stop the
+// blur at geometric and material edges. Works on linear radiance.
+pub fn denoise(
+    width: usize,
+    height: usize,
+    color: &[Vec3],
+    albedo: &[Vec3],
+    normal: &[Vec3],
+) -> Vec<Vec3> {
+    let mut current: Vec<Vec3> = color
+        .iter()
+        .zip(albedo)
+        .map(|(c, a)| *c / (*a + 0.001))
+        .collect();
+    let mut next = vec![Vec3::new(0.0, 0.0, 0.0); width * height];
+
+    for iteration in 0..ITERATIONS {
+        let step = 1 << iteration;
+        // Later iterations see an already smoothed image, so they need to be
+        // stricter about color differences.
+        let sigma_color = SIGMA_COLOR / (1 << iteration) as f32;
+
+        for y in 0..height {
+            for x in 0..width {
+                let i = y * width + x;
+                let mut sum = Vec3::new(0.0, 0.0, 0.0);
+                let mut weight_sum = 0.0;
+
+                for (ky, kernel_y) in KERNEL.iter().enumerate() {
+                    let sy = y as i64 + (ky as i64 - 2) * step;
+                    if sy < 0 || sy >= height as i64 {
+                        continue;
+                    }
+
+                    for (kx, kernel_x) in KERNEL.iter().enumerate() {
+                        let sx = x as i64 + (kx as i64 - 2) * step;
+                        if sx < 0 || sx >= width as i64 {
+                            continue;
+                        }
+
+                        let j = sy as usize * width + sx as usize;
+                        let w = kernel_x
+                            * kernel_y
+                            * color_weight(current[i], current[j], sigma_color)
+                            * edge_weight(normal[i], normal[j], SIGMA_NORMAL)
+                            * edge_weight(albedo[i], albedo[j], SIGMA_ALBEDO);
+
+                        sum = sum + w * current[j];
+                        weight_sum += w;
+                    }
+                }
+
+                next[i] = if weight_sum > 0.0 {
+                    sum / weight_sum
+                } else {
+                    current[i]
+                };
+            }
+        }
+
+        std::mem::swap(&mut current, &mut next);
+    }
+
+    return current
+        .iter()
+        .zip(albedo)
+        .map(|(c, a)| *c * (*a + 0.001))
+        .collect();
+}
+
+// Color differences are taken relative to the brightness of the center pixel so
+// bright and dark regions are smoothed alike.
+fn color_weight(center: Vec3, other: Vec3, sigma: f32) -> f32 {
+    let scale = f32::max(luminance(center), 0.01);
+    return edge_weight(center / scale, other / scale, sigma);
+}
+
+fn edge_weight(a: Vec3, b: Vec3, sigma: f32) -> f32 {
+    let d = a - b;
+    return f32::exp(-dot(d, d) / (sigma * sigma));
+}
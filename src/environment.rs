@@ -0,0 +1,196 @@
+use crate::color::luminance;
+use crate::consts::*;
+use crate::hdr::HdrImage;
+use crate::math::vec3::*;
+
+// Light arriving from infinitely far away, seen by rays that leave the scene.
+pub trait Environment {
+    fn eval(&self, direction: Vec3) -> Vec3;
+    // Picks a direction towards the environment for explicit light sampling,
+    // returning it with its radiance and solid angle pdf. Environments that
+    // can't be sampled return None and are only found by escaping rays.
+    fn sample(&self, u: f32, v: f32) -> Option<(Vec3, Vec3, f32)>;
+    fn pdf(&self, direction: Vec3) -> f32;
+    fn describe(&self) -> String;
+}
+
+// The sky gradient from the book.
+pub struct Gradient {}
+
+impl Environment for Gradient {
+    fn eval(&self, direction: Vec3) -> Vec3 {
+        let unit_direction = direction.unit_vector();
+        let t = 0.5 * (unit_direction.y + 1.0);
+        return (1.0 - t) * Vec3::new(1.0, 1.0, 1.0) + t * Vec3::new(0.5, 0.7, 1.0);
+    }
+
+    fn sample(&self, _u: f32, _v: f32) -> Option<(Vec3, Vec3, f32)> {
+        return None;
+    }
+
+    fn pdf(&self, _direction: Vec3) -> f32 {
+        return 0.0;
+    }
+
+    fn describe(&self) -> String {
+        return String::from("gradient");
+    }
+}
+
+// Equirectangular HDR image around the scene, +y up, with the image center
+// looking down -z. Directions are importance sampled by pixel luminance.
+pub struct EnvironmentMap {
+    source: String,
+    image: HdrImage,
+    // Radians around +y.
+    rotation: f32,
+    intensity: f32,
+    // Cumulative distributions over the rows, and over the pixels within each row.
+    marginal: Vec<f32>,
+    conditional: Vec<f32>,
+    // Pixel weight over the sum of all weights.
+    probability: Vec<f32>,
+}
+
+impl EnvironmentMap {
+    pub fn new(source: &str, image: HdrImage, rotation: f32, intensity: f32) -> Self {
+        let (width, height) = (image.width, image.height);
+
+        // Rows near the poles cover less solid angle, weighing pixels by
+        // sin(theta) keeps them from being oversampled.
+        let mut weights = vec![0.0; width * height];
+        for y in 0..height {
+            let sin_theta = f32::sin(PI * (y as f32 + 0.5) / height as f32);
+            for x in 0..width {
+                let i = y * width + x;
+                weights[i] = f32::max(luminance(image.pixels[i]), 0.0) * sin_theta;
+            }
+        }
+
+        let total: f32 = weights.iter().sum();
+        // A black image is sampled uniformly, which is as good as anything.
+        if total <= 0.0 {
+            for y in 0..height {
+                let sin_theta = f32::sin(PI * (y as f32 + 0.5) / height as f32);
+                for x in 0..width {
+                    weights[y * width + x] = sin_theta;
+                }
+            }
+        }
+        let total: f32 = weights.iter().sum();
+
+        let mut conditional = vec![0.0; width * height];
+        let mut marginal = vec![0.0; height];
+        let mut rows_so_far = 0.0;
+        for y in 0..height {
+            let mut row_sum = 0.0;
+            for x in 0..width {
+                row_sum += weights[y * width + x];
+                conditional[y * width + x] = row_sum;
+            }
+            for x in 0..width {
+                conditional[y * width + x] = if row_sum > 0.0 {
+                    conditional[y * width + x] / row_sum
+                } else {
+                    (x + 1) as f32 / width as f32
+                };
+            }
+            rows_so_far += row_sum;
+            marginal[y] = rows_so_far / total;
+        }
+
+        let probability = weights.iter().map(|w| w / total).collect();
+
+        return Self {
+            source: String::from(source),
+            image,
+            rotation,
+            intensity,
+            marginal,
+            conditional,
+            probability,
+        };
+    }
+
+    // Pixel coordinates in [0, width) x [0, height) of a direction.
+    fn to_image(&self, direction: Vec3) -> (f32, f32) {
+        let d = unit_vector(direction);
+        let phi = f32::atan2(d.x, -d.z) - self.rotation;
+        let theta = f32::acos(clamp(d.y, -1.0, 1.0));
+
+        let u = (phi / (2.0 * PI) + 0.5).rem_euclid(1.0);
+        let v = theta / PI;
+        return (
+            f32::min(u * self.image.width as f32, self.image.width as f32 - 1.0),
+            f32::min(v * self.image.height as f32, self.image.height as f32 - 1.0),
+        );
+    }
+
+    // The direction seen at a point of the image, undoing to_image.
+    fn direction_at(&self, x: f32, y: f32) -> Vec3 {
+        let phi = 2.0 * PI * (x / self.image.width as f32 - 0.5) + self.rotation;
+        let theta = PI * y / self.image.height as f32;
+        return Vec3::new(
+            f32::sin(theta) * f32::sin(phi),
+            f32::cos(theta),
+            -f32::sin(theta) * f32::cos(phi),
+        );
+    }
+
+    // Converts the probability of a pixel to a density over solid angle.
+    fn solid_angle_pdf(&self, i: usize, y: f32) -> f32 {
+        let sin_theta = f32::sin(PI * y / self.image.height as f32);
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        let pixels = (self.image.width * self.image.height) as f32;
+        return self.probability[i] * pixels / (2.0 * PI * PI * sin_theta);
+    }
+}
+
+// Index of the first entry of a cumulative distribution above u, with what is
+// left of u rescaled to [0, 1) within that entry.
+fn sample_cdf(cdf: &[f32], u: f32) -> (usize, f32) {
+    let i = cdf.partition_point(|c| *c <= u).min(cdf.len() - 1);
+    let low = if i == 0 { 0.0 } else { cdf[i - 1] };
+    let du = clamp((u - low) / f32::max(cdf[i] - low, 1e-8), 0.0, 0.999);
+    return (i, du);
+}
+
+impl Environment for EnvironmentMap {
+    fn eval(&self, direction: Vec3) -> Vec3 {
+        let (x, y) = self.to_image(direction);
+        return self.intensity * self.image.pixels[y as usize * self.image.width + x as usize];
+    }
+
+    fn sample(&self, u: f32, v: f32) -> Option<(Vec3, Vec3, f32)> {
+        let width = self.image.width;
+        let (row, dv) = sample_cdf(&self.marginal, v);
+        let (column, du) = sample_cdf(&self.conditional[row * width..(row + 1) * width], u);
+
+        let x = column as f32 + du;
+        let y = row as f32 + dv;
+        let pdf = self.solid_angle_pdf(row * width + column, y);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        let radiance = self.intensity * self.image.pixels[row * width + column];
+        return Some((self.direction_at(x, y), radiance, pdf));
+    }
+
+    fn pdf(&self, direction: Vec3) -> f32 {
+        let (x, y) = self.to_image(direction);
+        let i = y as usize * self.image.width + x as usize;
+        return self.solid_angle_pdf(i, y);
+    }
+
+    fn describe(&self) -> String {
+        return format!(
+            "map {} rotation {} intensity {}",
+            self.source,
+            self.rotation * 180.0 / PI,
+            self.intensity
+        );
+    }
+}
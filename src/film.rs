@@ -0,0 +1,259 @@
+use crate::color::{luminance, write_color};
+use crate::consts::*;
+use crate::filter::Filter;
+use crate::math::vec3::Vec3;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+// Accumulates radiance samples into pixels, weighting each sample by the
+// reconstruction filter and splatting it onto every pixel the filter covers.
+pub struct Film {
+    pub width: usize,
+    pub height: usize,
+    pub filter: Box<dyn Filter>,
+    pub sum: Vec<Vec3>,
+    pub weight: Vec<f32>,
+    // Statistics of the samples taken inside each pixel, used for adaptive
+    // sampling, independent of the filter.
+    pub samples: Vec<u32>,
+    pub lum_sum: Vec<f32>,
+    pub lum_sum_sq: Vec<f32>,
+    // First hit albedo and normal of each pixel's own samples, for the denoiser.
+    pub albedo_sum: Vec<Vec3>,
+    pub normal_sum: Vec<Vec3>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize, filter: Box<dyn Filter>) -> Self {
+        Self {
+            width,
+            height,
+            filter,
+            sum: vec![Vec3::new(0.0, 0.0, 0.0); width * height],
+            weight: vec![0.0; width * height],
+            samples: vec![0; width * height],
+            lum_sum: vec![0.0; width * height],
+            lum_sum_sq: vec![0.0; width * height],
+            albedo_sum: vec![Vec3::new(0.0, 0.0, 0.0); width * height],
+            normal_sum: vec![Vec3::new(0.0, 0.0, 0.0); width * height],
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for s in self.sum.iter_mut() {
+            *s = Vec3::new(0.0, 0.0, 0.0);
+        }
+        for w in self.weight.iter_mut() {
+            *w = 0.0;
+        }
+        for n in self.samples.iter_mut() {
+            *n = 0;
+        }
+        for l in self.lum_sum.iter_mut() {
+            *l = 0.0;
+        }
+        for l in self.lum_sum_sq.iter_mut() {
+            *l = 0.0;
+        }
+        for a in self.albedo_sum.iter_mut() {
+            *a = Vec3::new(0.0, 0.0, 0.0);
+        }
+        for n in self.normal_sum.iter_mut() {
+            *n = Vec3::new(0.0, 0.0, 0.0);
+        }
+    }
+
+    // Adds a sample taken at (x + dx, y + dy), with dx and dy in [0, 1).
+    pub fn add_sample(&mut self, x: usize, y: usize, dx: f32, dy: f32, color: Vec3) {
+        let i = y * self.width + x;
+        let l = luminance(color);
+        self.samples[i] += 1;
+        self.lum_sum[i] += l;
+        self.lum_sum_sq[i] += l * l;
+
+        let px = x as f32 + dx;
+        let py = y as f32 + dy;
+        let radius = self.filter.radius();
+
+        let x0 = f32::max(f32::ceil(px - radius - 0.5), 0.0) as usize;
+        let y0 = f32::max(f32::ceil(py - radius - 0.5), 0.0) as usize;
+        let x1 = f32::min(f32::floor(px + radius - 0.5), (self.width - 1) as f32);
+        let y1 = f32::min(f32::floor(py + radius - 0.5), (self.height - 1) as f32);
+        if x1 < 0.0 || y1 < 0.0 {
+            return;
+        }
+
+        for sy in y0..=y1 as usize {
+            for sx in x0..=x1 as usize {
+                let w = self
+                    .filter
+                    .evaluate(px - (sx as f32 + 0.5), py - (sy as f32 + 0.5));
+                if w == 0.0 {
+                    continue;
+                }
+
+                let j = sy * self.width + sx;
+                self.sum[j] = self.sum[j] + w * color;
+                self.weight[j] += w;
+            }
+        }
+    }
+
+    pub fn add_features(&mut self, x: usize, y: usize, albedo: Vec3, normal: Vec3) {
+        let i = y * self.width + x;
+        self.albedo_sum[i] = self.albedo_sum[i] + albedo;
+        self.normal_sum[i] = self.normal_sum[i] + normal;
+    }
+
+    // The filtered linear radiance of every pixel, black where nothing landed yet.
+    pub fn resolve(&self) -> Vec<Vec3> {
+        return self
+            .sum
+            .iter()
+            .zip(&self.weight)
+            .map(|(sum, weight)| {
+                if *weight <= 0.0 {
+                    return Vec3::new(0.0, 0.0, 0.0);
+                }
+                let c = *sum / *weight;
+                // Negative filter lobes can push a channel below zero.
+                return Vec3::new(f32::max(c.x, 0.0), f32::max(c.y, 0.0), f32::max(c.z, 0.0));
+            })
+            .collect();
+    }
+
+    // Per pixel average albedo and normal.
+    pub fn features(&self) -> (Vec<Vec3>, Vec<Vec3>) {
+        let mut albedo = vec![Vec3::new(0.0, 0.0, 0.0); self.width * self.height];
+        let mut normal = vec![Vec3::new(0.0, 0.0, 0.0); self.width * self.height];
+        for i in 0..albedo.len() {
+            if self.samples[i] > 0 {
+                let n = self.samples[i] as f32;
+                albedo[i] = self.albedo_sum[i] / n;
+                normal[i] = self.normal_sum[i] / n;
+            }
+        }
+        return (albedo, normal);
+    }
+
+    pub fn sample_count(&self, x: usize, y: usize) -> u32 {
+        return self.samples[y * self.width + x];
+    }
+
+    // Standard error of the pixel's mean luminance relative to the mean itself.
+    pub fn relative_error(&self, x: usize, y: usize) -> f32 {
+        let i = y * self.width + x;
+        let n = self.samples[i] as f32;
+        if n < 2.0 {
+            return INFINITY;
+        }
+
+        let mean = self.lum_sum[i] / n;
+        let variance = f32::max(self.lum_sum_sq[i] / n - mean * mean, 0.0) * n / (n - 1.0);
+
+        // The floor keeps near black pixels from demanding endless samples.
+        return f32::sqrt(variance / n) / f32::max(mean, 0.01);
+    }
+
+    pub fn converged(&self, x: usize, y: usize, min_samples: u32, threshold: f32) -> bool {
+        let i = y * self.width + x;
+        return self.samples[i] >= min_samples && self.relative_error(x, y) < threshold;
+    }
+
+    // Writes the filtered, gamma corrected image as RGBA8 with row 0 at the top.
+    // Pixels without samples keep whatever the previous image left there.
+    pub fn write_rgba(&self, pixels: &mut [u8]) {
+        self.write_image_rgba(&self.resolve(), pixels);
+    }
+
+    // Same as write_rgba for an image derived from this film, like a denoised one.
+    pub fn write_image_rgba(&self, image: &[Vec3], pixels: &mut [u8]) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let i = y * self.width + x;
+                if self.samples[i] == 0 || self.weight[i] <= 0.0 {
+                    continue;
+                }
+
+                let o = ((self.height - 1 - y) * self.width + x) * 4;
+
+                let mut pixel_color = image[i];
+                write_color(&mut pixel_color, 1);
+
+                pixels[o] = pixel_color.x as u8;
+                pixels[o + 1] = pixel_color.y as u8;
+                pixels[o + 2] = pixel_color.z as u8;
+                pixels[o + 3] = 255;
+            }
+        }
+    }
+
+    // Gamma corrected RGB8 with row 0 at the top, as image files store it.
+    pub fn rgb8(&self, image: &[Vec3]) -> Vec<u8> {
+        let mut rgb = Vec::with_capacity(self.width * self.height * 3);
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let mut pixel_color = image[y * self.width + x];
+                write_color(&mut pixel_color, 1);
+                rgb.push(pixel_color.x as u8);
+                rgb.push(pixel_color.y as u8);
+                rgb.push(pixel_color.z as u8);
+            }
+        }
+        return rgb;
+    }
+
+    pub fn save_ppm(&self, path: &str, image: &[Vec3]) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "P3\n{} {}\n255", self.width, self.height)?;
+
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let mut pixel_color = image[y * self.width + x];
+                write_color(&mut pixel_color, 1);
+                writeln!(
+                    out,
+                    "{} {} {}",
+                    pixel_color.x as u8, pixel_color.y as u8, pixel_color.z as u8
+                )?;
+            }
+        }
+
+        return out.flush();
+    }
+
+    // Writes how many samples each pixel took, from blue (none) to red (max_samples).
+    pub fn write_heatmap_rgba(&self, pixels: &mut [u8], max_samples: u32) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let o = ((self.height - 1 - y) * self.width + x) * 4;
+                let color = heatmap_color(self.samples[y * self.width + x], max_samples);
+
+                pixels[o] = color.x as u8;
+                pixels[o + 1] = color.y as u8;
+                pixels[o + 2] = color.z as u8;
+                pixels[o + 3] = 255;
+            }
+        }
+    }
+
+    pub fn save_heatmap_ppm(&self, path: &str, max_samples: u32) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "P3\n{} {}\n255", self.width, self.height)?;
+
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let color = heatmap_color(self.samples[y * self.width + x], max_samples);
+                writeln!(out, "{} {} {}", color.x as u8, color.y as u8, color.z as u8)?;
+            }
+        }
+
+        return out.flush();
+    }
+}
+
+fn heatmap_color(samples: u32, max_samples: u32) -> Vec3 {
+    let t = clamp(samples as f32 / max_samples as f32, 0.0, 1.0);
+    return 255.0 * Vec3::new(t, 4.0 * t * (1.0 - t), 1.0 - t);
+}
@@ -0,0 +1,168 @@
+use crate::consts::PI;
+
+// Pixel reconstruction filter. Offsets are in pixels from the pixel center and
+// the filter is zero outside [-radius, radius] on both axes.
+pub trait Filter {
+    fn radius(&self) -> f32;
+    fn evaluate(&self, dx: f32, dy: f32) -> f32;
+}
+
+pub fn from_name(name: &str) -> Option<Box<dyn Filter>> {
+    return match name {
+        "box" => Some(Box::new(BoxFilter::new(0.5))),
+        "tent" => Some(Box::new(TentFilter::new(1.0))),
+        "gaussian" => Some(Box::new(GaussianFilter::new(1.5, 0.5))),
+        "mitchell" => Some(Box::new(MitchellFilter::new(2.0, 1.0 / 3.0, 1.0 / 3.0))),
+        "lanczos" => Some(Box::new(LanczosFilter::new(2.0))),
+        _ => None,
+    };
+}
+
+pub struct BoxFilter {
+    radius: f32,
+}
+
+impl BoxFilter {
+    pub fn new(radius: f32) -> Self {
+        Self { radius }
+    }
+}
+
+impl Filter for BoxFilter {
+    fn radius(&self) -> f32 {
+        return self.radius;
+    }
+
+    fn evaluate(&self, dx: f32, dy: f32) -> f32 {
+        if f32::abs(dx) <= self.radius && f32::abs(dy) <= self.radius {
+            return 1.0;
+        }
+        return 0.0;
+    }
+}
+
+pub struct TentFilter {
+    radius: f32,
+}
+
+impl TentFilter {
+    pub fn new(radius: f32) -> Self {
+        Self { radius }
+    }
+}
+
+impl Filter for TentFilter {
+    fn radius(&self) -> f32 {
+        return self.radius;
+    }
+
+    fn evaluate(&self, dx: f32, dy: f32) -> f32 {
+        return f32::max(0.0, self.radius - f32::abs(dx))
+            * f32::max(0.0, self.radius - f32::abs(dy));
+    }
+}
+
+pub struct GaussianFilter {
+    radius: f32,
+    sigma: f32,
+}
+
+impl GaussianFilter {
+    pub fn new(radius: f32, sigma: f32) -> Self {
+        Self { radius, sigma }
+    }
+
+    // Shifted down so the filter reaches zero at the radius instead of being cut off.
+    fn gaussian(&self, d: f32) -> f32 {
+        let g = |x: f32| f32::exp(-x * x / (2.0 * self.sigma * self.sigma));
+        return f32::max(0.0, g(d) - g(self.radius));
+    }
+}
+
+impl Filter for GaussianFilter {
+    fn radius(&self) -> f32 {
+        return self.radius;
+    }
+
+    fn evaluate(&self, dx: f32, dy: f32) -> f32 {
+        return self.gaussian(dx) * self.gaussian(dy);
+    }
+}
+
+pub struct MitchellFilter {
+    radius: f32,
+    b: f32,
+    c: f32,
+}
+
+impl MitchellFilter {
+    pub fn new(radius: f32, b: f32, c: f32) -> Self {
+        Self { radius, b, c }
+    }
+
+    // The 1D Mitchell-Netravali cubic, defined on [-2, 2].
+    fn mitchell(&self, x: f32) -> f32 {
+        let x = f32::abs(x);
+        let (b, c) = (self.b, self.c);
+
+        if x < 1.0 {
+            return ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                + (6.0 - 2.0 * b))
+                / 6.0;
+        }
+        if x < 2.0 {
+            return ((-b - 6.0 * c) * x * x * x
+                + (6.0 * b + 30.0 * c) * x * x
+                + (-12.0 * b - 48.0 * c) * x
+                + (8.0 * b + 24.0 * c))
+                / 6.0;
+        }
+        return 0.0;
+    }
+}
+
+impl Filter for MitchellFilter {
+    fn radius(&self) -> f32 {
+        return self.radius;
+    }
+
+    fn evaluate(&self, dx: f32, dy: f32) -> f32 {
+        let scale = 2.0 / self.radius;
+        return self.mitchell(dx * scale) * self.mitchell(dy * scale);
+    }
+}
+
+pub struct LanczosFilter {
+    radius: f32,
+}
+
+impl LanczosFilter {
+    pub fn new(radius: f32) -> Self {
+        Self { radius }
+    }
+
+    fn windowed_sinc(&self, x: f32) -> f32 {
+        if f32::abs(x) > self.radius {
+            return 0.0;
+        }
+        return sinc(x) * sinc(x / self.radius);
+    }
+}
+
+impl Filter for LanczosFilter {
+    fn radius(&self) -> f32 {
+        return self.radius;
+    }
+
+    fn evaluate(&self, dx: f32, dy: f32) -> f32 {
+        return self.windowed_sinc(dx) * self.windowed_sinc(dy);
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if f32::abs(x) < 1e-5 {
+        return 1.0;
+    }
+    return f32::sin(PI * x) / (PI * x);
+}
@@ -0,0 +1,603 @@
+use crate::inflate::zlib_decompress;
+use crate::math::vec3::Vec3;
+
+use std::fs;
+
+// Linear floating point image with row 0 at the top.
+pub struct HdrImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Vec3>,
+}
+
+// Loads a Radiance .hdr or an OpenEXR .exr file, picked by extension.
+pub fn load(path: &str) -> Result<HdrImage, String> {
+    let data = fs::read(path).map_err(|err| err.to_string())?;
+    let lower = path.to_lowercase();
+    if lower.ends_with(".hdr") || lower.ends_with(".pic") {
+        return load_radiance(&data);
+    }
+    if lower.ends_with(".exr") {
+        return load_exr(&data);
+    }
+    return Err(String::from("expected a .hdr or .exr file"));
+}
+
+// Radiance RGBE (Ward 1991), flat or with the run length encoded scanlines
+// every modern writer produces.
+fn load_radiance(data: &[u8]) -> Result<HdrImage, String> {
+    let mut pos = 0;
+    let read_line = |pos: &mut usize| -> Result<String, String> {
+        let start = *pos;
+        while *pos < data.len() && data[*pos] != b'\n' {
+            *pos += 1;
+        }
+        if *pos >= data.len() {
+            return Err(String::from("truncated header"));
+        }
+        *pos += 1;
+        return Ok(String::from_utf8_lossy(&data[start..*pos - 1]).into_owned());
+    };
+
+    let magic = read_line(&mut pos)?;
+    if !magic.starts_with("#?") {
+        return Err(String::from("not a Radiance file"));
+    }
+    loop {
+        let line = read_line(&mut pos)?;
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with("FORMAT=") && line != "FORMAT=32-bit_rle_rgbe" {
+            return Err(format!("unsupported {}", line));
+        }
+    }
+
+    // Only the standard orientation, top to bottom and left to right.
+    let resolution = read_line(&mut pos)?;
+    let words: Vec<&str> = resolution.split_whitespace().collect();
+    let (height, width) = match words[..] {
+        ["-Y", h, "+X", w] => (
+            h.parse::<usize>().map_err(|err| err.to_string())?,
+            w.parse::<usize>().map_err(|err| err.to_string())?,
+        ),
+        _ => return Err(format!("unsupported resolution line {}", resolution)),
+    };
+
+    if width == 0 || height == 0 {
+        return Err(String::from("empty image"));
+    }
+
+    // Sizes come from the header, so they are checked against the file
+    // before anything is allocated. Run length encoded rows take at least
+    // their 4 byte header and a run of 2 bytes per 127 pixels per channel,
+    // flat ones 4 bytes per pixel.
+    let smallest_row = if (8..0x8000).contains(&width) {
+        4 + 8 * width.div_ceil(127)
+    } else {
+        width.checked_mul(4).ok_or("the image is too large")?
+    };
+    let fits = height
+        .checked_mul(smallest_row)
+        .is_some_and(|size| size <= data.len() - pos);
+    if !fits {
+        return Err(String::from("the image is larger than the file"));
+    }
+
+    let mut rgbe = vec![0u8; width * height * 4];
+    for y in 0..height {
+        let row = &mut rgbe[y * width * 4..(y + 1) * width * 4];
+        let next = data.get(pos..pos + 4).ok_or("truncated pixel data")?;
+
+        let is_rle = (8..0x8000).contains(&width) && next[0] == 2 && next[1] == 2;
+        if !is_rle || ((next[2] as usize) << 8 | next[3] as usize) != width {
+            // Flat pixels. Old style run lengths aren't supported.
+            let flat = data
+                .get(pos..pos + width * 4)
+                .ok_or("truncated pixel data")?;
+            row.copy_from_slice(flat);
+            pos += width * 4;
+            continue;
+        }
+
+        pos += 4;
+        for channel in 0..4 {
+            let mut x = 0;
+            while x < width {
+                let count = *data.get(pos).ok_or("truncated pixel data")? as usize;
+                pos += 1;
+                if count > 128 {
+                    let count = count - 128;
+                    let value = *data.get(pos).ok_or("truncated pixel data")?;
+                    pos += 1;
+                    if x + count > width {
+                        return Err(String::from("bad run length"));
+                    }
+                    for _ in 0..count {
+                        row[x * 4 + channel] = value;
+                        x += 1;
+                    }
+                } else {
+                    if count == 0 || x + count > width {
+                        return Err(String::from("bad run length"));
+                    }
+                    let values = data.get(pos..pos + count).ok_or("truncated pixel data")?;
+                    for value in values {
+                        row[x * 4 + channel] = *value;
+                        x += 1;
+                    }
+                    pos += count;
+                }
+            }
+        }
+    }
+
+    let pixels = rgbe
+        .chunks(4)
+        .map(|p| {
+            if p[3] == 0 {
+                return Vec3::new(0.0, 0.0, 0.0);
+            }
+            let scale = f32::powi(2.0, p[3] as i32 - 136);
+            return Vec3::new(
+                p[0] as f32 * scale,
+                p[1] as f32 * scale,
+                p[2] as f32 * scale,
+            );
+        })
+        .collect();
+
+    return Ok(HdrImage {
+        width,
+        height,
+        pixels,
+    });
+}
+
+fn half_to_f32(h: u16) -> f32 {
+    let sign = if h & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((h >> 10) & 0x1f) as i32;
+    let mantissa = (h & 0x3ff) as f32;
+    return match exponent {
+        0 => sign * mantissa * f32::powi(2.0, -24),
+        31 => {
+            if mantissa == 0.0 {
+                sign * f32::INFINITY
+            } else {
+                f32::NAN
+            }
+        }
+        _ => sign * (1.0 + mantissa / 1024.0) * f32::powi(2.0, exponent - 15),
+    };
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .pos
+            .checked_add(count)
+            .and_then(|end| self.data.get(self.pos..end))
+            .ok_or("truncated file")?;
+        self.pos += count;
+        return Ok(bytes);
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        let b = self.bytes(4)?;
+        return Ok(i32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        let b = self.bytes(8)?;
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(b);
+        return Ok(u64::from_le_bytes(bytes));
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let start = self.pos;
+        while *self.data.get(self.pos).ok_or("truncated file")? != 0 {
+            self.pos += 1;
+        }
+        self.pos += 1;
+        return Ok(String::from_utf8_lossy(&self.data[start..self.pos - 1]).into_owned());
+    }
+}
+
+// Single part scanline OpenEXR with uncompressed, RLE or ZIP compressed
+// blocks. Tiled files and the wavelet (PIZ) and lossy compressions aren't
+// supported; re-save those with ZIP compression.
+fn load_exr(data: &[u8]) -> Result<HdrImage, String> {
+    let mut reader = Reader { data, pos: 0 };
+    if reader.bytes(4)? != [0x76, 0x2f, 0x31, 0x01] {
+        return Err(String::from("not an OpenEXR file"));
+    }
+    let version = reader.i32()?;
+    if version & 0x200 != 0 || version & 0x1000 != 0 {
+        return Err(String::from(
+            "tiled and multi part EXR files aren't supported",
+        ));
+    }
+
+    // Channel names and pixel types, sorted by name as in the file.
+    let mut channels: Vec<(String, i32)> = Vec::new();
+    let mut compression = 0;
+    let mut window = (0, 0, 0, 0);
+    loop {
+        let name = reader.string()?;
+        if name.is_empty() {
+            break;
+        }
+        let kind = reader.string()?;
+        let size = reader.i32()? as usize;
+        let mut value = Reader {
+            data: reader.bytes(size)?,
+            pos: 0,
+        };
+
+        match (name.as_str(), kind.as_str()) {
+            ("channels", "chlist") => loop {
+                let channel = value.string()?;
+                if channel.is_empty() {
+                    break;
+                }
+                let pixel_type = value.i32()?;
+                // pLinear, reserved and the x and y sampling.
+                value.bytes(12)?;
+                channels.push((channel, pixel_type));
+            },
+            ("compression", "compression") => compression = value.bytes(1)?[0],
+            ("dataWindow", "box2i") => {
+                window = (value.i32()?, value.i32()?, value.i32()?, value.i32()?);
+            }
+            _ => {}
+        }
+    }
+
+    let lines_per_block = match compression {
+        0..=2 => 1,
+        3 => 16,
+        _ => {
+            return Err(String::from(
+                "unsupported EXR compression, use none, RLE or ZIP",
+            ))
+        }
+    };
+
+    if window.2 < window.0 || window.3 < window.1 {
+        return Err(String::from("empty data window"));
+    }
+    let width = (window.2 as i64 - window.0 as i64 + 1) as usize;
+    let height = (window.3 as i64 - window.1 as i64 + 1) as usize;
+
+    let channel_size = |pixel_type: i32| if pixel_type == 1 { 2usize } else { 4 };
+    let row_size = channels
+        .iter()
+        .try_fold(0usize, |size, (_, t)| {
+            channel_size(*t)
+                .checked_mul(width)
+                .and_then(|channel| size.checked_add(channel))
+        })
+        .ok_or("the data window is too large")?;
+    let image_size = row_size
+        .checked_mul(height)
+        .ok_or("the data window is too large")?;
+
+    // Luminance only files are shown as gray.
+    let index_of = |name: &str| channels.iter().position(|(n, _)| n == name);
+    let sources = match (index_of("R"), index_of("G"), index_of("B"), index_of("Y")) {
+        (Some(r), Some(g), Some(b), _) => [r, g, b],
+        (_, _, _, Some(y)) => [y, y, y],
+        _ => return Err(String::from("the EXR has no R, G and B or Y channels")),
+    };
+
+    // Where each of the three sources starts within a row, and its type.
+    let layout: Vec<(usize, i32)> = sources
+        .iter()
+        .map(|&source| {
+            let start = channels[..source]
+                .iter()
+                .map(|(_, t)| channel_size(*t) * width)
+                .sum();
+            (start, channels[source].1)
+        })
+        .collect();
+
+    // Sizes come from the header, so they are checked against the file
+    // before anything is allocated: every block has an offset and a header
+    // of 8 bytes each, and its pixels can't shrink by more than RLE's 64
+    // times or deflate's 1032 times.
+    let block_count = height.div_ceil(lines_per_block);
+    let expansion = match compression {
+        0 => 1,
+        1 => 64,
+        _ => 1032,
+    };
+    let remaining = data.len() - reader.pos;
+    let headers_fit = block_count
+        .checked_mul(16)
+        .is_some_and(|size| size <= remaining);
+    if !headers_fit || image_size / expansion > remaining {
+        return Err(String::from("the data window is larger than the file"));
+    }
+
+    let mut offsets = Vec::new();
+    for _ in 0..block_count {
+        offsets.push(reader.u64()? as usize);
+    }
+
+    let mut pixels = vec![Vec3::new(0.0, 0.0, 0.0); width * height];
+    for offset in offsets {
+        let mut block = Reader { data, pos: offset };
+        let first_line = block.i32()? as i64 - window.1 as i64;
+        if first_line < 0 || first_line >= height as i64 {
+            return Err(String::from("EXR block outside the data window"));
+        }
+        let first_line = first_line as usize;
+        let size = block.i32()? as usize;
+        let packed = block.bytes(size)?;
+
+        let lines = usize::min(lines_per_block, height - first_line);
+        let expected = row_size * lines;
+        let raw = if size == expected {
+            packed.to_vec()
+        } else {
+            match compression {
+                1 => unpredict(&deinterleave_rle(packed, expected)?),
+                2 | 3 => unpredict(&zlib_decompress(packed)?),
+                _ => return Err(String::from("bad uncompressed block size")),
+            }
+        };
+        if raw.len() < expected {
+            return Err(String::from("short EXR block"));
+        }
+
+        for line in 0..lines {
+            let y = first_line + line;
+            let row = &raw[line * row_size..(line + 1) * row_size];
+            for x in 0..width {
+                let mut rgb = [0.0f32; 3];
+                for (c, &(start, pixel_type)) in layout.iter().enumerate() {
+                    let o = start + x * channel_size(pixel_type);
+                    rgb[c] = match pixel_type {
+                        0 => {
+                            u32::from_le_bytes([row[o], row[o + 1], row[o + 2], row[o + 3]]) as f32
+                        }
+                        1 => half_to_f32(u16::from_le_bytes([row[o], row[o + 1]])),
+                        _ => f32::from_le_bytes([row[o], row[o + 1], row[o + 2], row[o + 3]]),
+                    };
+                }
+                pixels[y * width + x] = Vec3::new(rgb[0], rgb[1], rgb[2]);
+            }
+        }
+    }
+
+    return Ok(HdrImage {
+        width,
+        height,
+        pixels,
+    });
+}
+
+// EXR's RLE: a negative count is followed by that many literal bytes, a
+// positive one by a byte repeated count + 1 times.
+fn deinterleave_rle(packed: &[u8], expected: usize) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+    while pos < packed.len() {
+        if out.len() > expected {
+            return Err(String::from("RLE block larger than expected"));
+        }
+        let count = packed[pos] as i8;
+        pos += 1;
+        if count < 0 {
+            let n = (-(count as i32)) as usize;
+            out.extend_from_slice(packed.get(pos..pos + n).ok_or("truncated RLE block")?);
+            pos += n;
+        } else {
+            let value = *packed.get(pos).ok_or("truncated RLE block")?;
+            pos += 1;
+            for _ in 0..count as usize + 1 {
+                out.push(value);
+            }
+        }
+    }
+    return Ok(out);
+}
+
+// Undoes the delta predictor and the split of even and odd bytes that EXR
+// applies before RLE and ZIP compression.
+fn unpredict(data: &[u8]) -> Vec<u8> {
+    let mut deltas = data.to_vec();
+    for i in 1..deltas.len() {
+        deltas[i] = (deltas[i - 1] as i32 + deltas[i] as i32 - 128) as u8;
+    }
+
+    let half = deltas.len().div_ceil(2);
+    let mut out = Vec::with_capacity(deltas.len());
+    for i in 0..deltas.len() {
+        if i % 2 == 0 {
+            out.push(deltas[i / 2]);
+        } else {
+            out.push(deltas[half + i / 2]);
+        }
+    }
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // What EXR does to a block before compressing it, undone by unpredict.
+    fn predict(raw: &[u8]) -> Vec<u8> {
+        let half = raw.len().div_ceil(2);
+        let mut split = vec![0u8; raw.len()];
+        for (i, &byte) in raw.iter().enumerate() {
+            if i % 2 == 0 {
+                split[i / 2] = byte;
+            } else {
+                split[half + i / 2] = byte;
+            }
+        }
+
+        let mut out = split.clone();
+        for i in 1..split.len() {
+            out[i] = (split[i] as i32 - split[i - 1] as i32 + 128) as u8;
+        }
+        return out;
+    }
+
+    // A zlib stream of one stored deflate block.
+    fn zlib_stored(data: &[u8]) -> Vec<u8> {
+        let length = data.len() as u16;
+        let mut out = vec![0x78, 0x01, 0x01];
+        out.extend_from_slice(&length.to_le_bytes());
+        out.extend_from_slice(&(!length).to_le_bytes());
+        out.extend_from_slice(data);
+        out.extend_from_slice(&[0, 0, 0, 0]);
+        return out;
+    }
+
+    fn attribute(out: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+        for text in [name, kind] {
+            out.extend_from_slice(text.as_bytes());
+            out.push(0);
+        }
+        out.extend_from_slice(&(value.len() as i32).to_le_bytes());
+        out.extend_from_slice(value);
+    }
+
+    // A 2x2 luminance EXR of 32 bit floats, with one block per line given as
+    // its first line and its bytes.
+    fn exr(compression: u8, blocks: &[(i32, Vec<u8>)]) -> Vec<u8> {
+        return exr_window(compression, [0, 0, 1, 1], blocks);
+    }
+
+    fn exr_window(compression: u8, window: [i32; 4], blocks: &[(i32, Vec<u8>)]) -> Vec<u8> {
+        let mut out = vec![0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0];
+
+        let mut channels = b"Y\0".to_vec();
+        channels.extend_from_slice(&2i32.to_le_bytes());
+        channels.extend_from_slice(&[0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 0]);
+        attribute(&mut out, "channels", "chlist", &channels);
+        attribute(&mut out, "compression", "compression", &[compression]);
+        let window: Vec<u8> = window.iter().flat_map(|v| v.to_le_bytes()).collect();
+        attribute(&mut out, "dataWindow", "box2i", &window);
+        out.push(0);
+
+        let mut offset = out.len() + 8 * blocks.len();
+        for (_, bytes) in blocks {
+            out.extend_from_slice(&(offset as u64).to_le_bytes());
+            offset += 8 + bytes.len();
+        }
+        for (first_line, bytes) in blocks {
+            out.extend_from_slice(&first_line.to_le_bytes());
+            out.extend_from_slice(&(bytes.len() as i32).to_le_bytes());
+            out.extend_from_slice(bytes);
+        }
+        return out;
+    }
+
+    fn line(a: f32, b: f32) -> Vec<u8> {
+        return [a.to_le_bytes(), b.to_le_bytes()].concat();
+    }
+
+    fn luminance(image: &HdrImage) -> Vec<f32> {
+        return image.pixels.iter().map(|p| p.x).collect();
+    }
+
+    #[test]
+    fn rle_expands_runs_and_literals() {
+        let packed = [(-3i8) as u8, 1, 2, 3, 2, 9];
+        assert_eq!(deinterleave_rle(&packed, 6).unwrap(), [1, 2, 3, 9, 9, 9]);
+        assert!(deinterleave_rle(&[(-3i8) as u8, 1], 3).is_err());
+        assert!(deinterleave_rle(&[4], 5).is_err());
+        assert!(deinterleave_rle(&[127, 0, 127, 0], 4).is_err());
+    }
+
+    #[test]
+    fn unpredict_undoes_predict() {
+        for raw in [
+            vec![],
+            vec![7],
+            vec![10, 200, 30, 40, 250],
+            vec![0, 255, 128, 1],
+        ] {
+            assert_eq!(unpredict(&predict(&raw)), raw);
+        }
+    }
+
+    #[test]
+    fn exr_blocks_decode() {
+        let lines = [line(1.0, 2.0), line(3.0, 4.0)];
+        let uncompressed = exr(0, &[(0, lines[0].clone()), (1, lines[1].clone())]);
+        let rle_block = |raw: &Vec<u8>| -> Vec<u8> {
+            let mut packed = vec![(-(raw.len() as i8)) as u8];
+            packed.extend(predict(raw));
+            return packed;
+        };
+        let rle = exr(1, &[(0, rle_block(&lines[0])), (1, rle_block(&lines[1]))]);
+        let zip_block = |raw: &Vec<u8>| zlib_stored(&predict(raw));
+        let zip = exr(2, &[(1, zip_block(&lines[1])), (0, zip_block(&lines[0]))]);
+
+        for data in [uncompressed, rle, zip] {
+            let image = load_exr(&data).unwrap();
+            assert_eq!((image.width, image.height), (2, 2));
+            assert_eq!(luminance(&image), [1.0, 2.0, 3.0, 4.0]);
+        }
+    }
+
+    #[test]
+    fn exr_blocks_outside_the_window_are_rejected() {
+        for first_line in [-1, 2, i32::MIN, i32::MAX] {
+            let data = exr(0, &[(0, line(1.0, 2.0)), (first_line, line(3.0, 4.0))]);
+            assert!(load_exr(&data).is_err());
+        }
+    }
+
+    #[test]
+    fn windows_larger_than_the_file_are_rejected() {
+        let blocks = [(0, line(1.0, 2.0)), (1, line(3.0, 4.0))];
+        for window in [
+            [0, 0, i32::MAX, 1],
+            [i32::MIN, 0, i32::MAX, 1],
+            [0, i32::MIN, 1, i32::MAX],
+            [0, 0, 100_000, 100_000],
+        ] {
+            for compression in [0, 1, 2, 3] {
+                assert!(load_exr(&exr_window(compression, window, &blocks)).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn radiance_sizes_larger_than_the_file_are_rejected() {
+        for resolution in [
+            "-Y 100000 +X 100000",
+            "-Y 1 +X 18446744073709551615",
+            "-Y 0 +X 4",
+        ] {
+            let data = format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n{}\n", resolution);
+            assert!(load_radiance(data.as_bytes()).is_err());
+        }
+
+        let mut data = b"#?RADIANCE\n\n-Y 1 +X 2\n".to_vec();
+        data.extend_from_slice(&[128, 64, 32, 129, 128, 64, 32, 129]);
+        let image = load_radiance(&data).unwrap();
+        assert_eq!(image.pixels[1].x, 1.0);
+    }
+
+    #[test]
+    fn truncated_exr_is_rejected() {
+        let data = exr(0, &[(0, line(1.0, 2.0)), (1, line(3.0, 4.0))]);
+        for length in [3, 20, data.len() - 1] {
+            assert!(load_exr(&data[..length]).is_err());
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use crate::consts::random;
 use crate::material::*;
 use crate::math::vec3::*;
 use crate::ray::Ray;
@@ -7,10 +8,22 @@ use std::sync::Arc;
 #[derive(Default, Clone)]
 pub struct HitRecord {
     pub point: Vec3,
+    // Shading normal, facing the ray. Normal and bump maps bend it, while the
+    // geometric normal stays the surface's own, facing the same side.
     pub normal: Vec3,
+    pub geometric_normal: Vec3,
     pub t: f32,
+    // Surface coordinates for textures, both in [0, 1].
+    pub u: f32,
+    pub v: f32,
+    // How the point moves along the surface with u and v.
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
     pub front_face: bool,
     pub mat: Option<Arc<dyn Material>>,
+    // Wavelength of the ray that hit in nanometers, 0 when it carries none.
+    // Shapes leave it alone, it is filled in by whoever traced the ray.
+    pub wavelength: f32,
 }
 
 impl HitRecord {
@@ -21,9 +34,36 @@ impl HitRecord {
         } else {
             -*outward_normal
         };
+        self.geometric_normal = self.normal;
+    }
+
+    // Whether the hit counts, given its material's opacity. Partly
+    // transparent spots let rays through at random as often as they are
+    // transparent. Shapes run this while they look for hits, where the pixel's
+    // sampler isn't at hand, so the choice comes from the global generator,
+    // which is reseeded every pass and keeps renders repeatable. Each surface
+    // a ray meets also needs a fresh number, more than one sample per ray
+    // would give.
+    pub fn alpha_test(&self) -> bool {
+        let opacity = match &self.mat {
+            Some(mat) => mat.opacity(self),
+            None => return true,
+        };
+        return opacity >= 1.0 || (opacity > 0.0 && random() < opacity);
+    }
+
+    // Origin for rays leaving towards direction, nudged off the surface to the
+    // side they leave by, so they don't find the surface again right away.
+    pub fn offset_point(&self, direction: Vec3) -> Vec3 {
+        let offset = 1e-4 * self.geometric_normal;
+        if dot(direction, self.geometric_normal) < 0.0 {
+            return self.point - offset;
+        }
+        return self.point + offset;
     }
 }
 
 pub trait Hittable {
     fn hit(&self, ray: &Ray, t_min: f32, t_max: f32, hit_record: &mut HitRecord) -> bool;
+    fn describe(&self) -> String;
 }
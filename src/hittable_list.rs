@@ -17,6 +17,25 @@ impl HittableList {
     pub fn clear(&mut self) {
         self.objects.clear();
     }
+    pub fn into_objects(self) -> Vec<Box<dyn Hittable>> {
+        return self.objects;
+    }
+
+    // Returns the closest object along the ray together with its hit record.
+    pub fn pick(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(&dyn Hittable, HitRecord)> {
+        let mut temp_rec: HitRecord = Default::default();
+        let mut closest: Option<(&dyn Hittable, HitRecord)> = None;
+        let mut closest_so_far = t_max;
+
+        for object in &self.objects {
+            if object.hit(&ray, t_min, closest_so_far, &mut temp_rec) {
+                closest_so_far = temp_rec.t;
+                closest = Some((object.as_ref(), temp_rec.clone()));
+            }
+        }
+
+        return closest;
+    }
 }
 
 impl Hittable for HittableList {
@@ -35,4 +54,13 @@ impl Hittable for HittableList {
 
         return hit_anything;
     }
+
+    fn describe(&self) -> String {
+        let mut description = format!("HittableList with {} objects", self.objects.len());
+        for object in &self.objects {
+            description.push_str("\n    ");
+            description.push_str(&object.describe());
+        }
+        return description;
+    }
 }
@@ -0,0 +1,178 @@
+use crate::consts::*;
+use crate::math::vec3::*;
+
+use std::fs;
+
+// Goniometric distribution of a luminaire from an IES LM-63 file. Only type C
+// photometry is read, which is what almost every fixture uses: vertical angles
+// go from 0 straight down to 180 straight up, horizontal angles around the
+// vertical axis.
+pub struct IesProfile {
+    pub source: String,
+    vertical: Vec<f32>,
+    horizontal: Vec<f32>,
+    // candela[h * vertical.len() + v], scaled so the brightest direction is 1.
+    candela: Vec<f32>,
+}
+
+impl IesProfile {
+    pub fn load(path: &str) -> Result<IesProfile, String> {
+        let text = fs::read(path).map_err(|err| err.to_string())?;
+        return IesProfile::parse(path, &String::from_utf8_lossy(&text));
+    }
+
+    pub fn parse(source: &str, text: &str) -> Result<IesProfile, String> {
+        // Everything up to the TILT line is keywords, after it come numbers.
+        let mut lines = text.lines();
+        let tilt = loop {
+            match lines.next() {
+                Some(line) if line.trim_start().starts_with("TILT=") => {
+                    break line.trim_start()["TILT=".len()..].trim().to_string();
+                }
+                Some(_) => continue,
+                None => return Err(String::from("missing TILT line")),
+            }
+        };
+
+        let rest: Vec<&str> = lines.collect();
+        let mut numbers = rest
+            .iter()
+            .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+            .filter(|word| !word.is_empty())
+            .map(|word| {
+                word.parse::<f32>()
+                    .map_err(|err| format!("invalid number {}: {}", word, err))
+            });
+        let mut next = || -> Result<f32, String> {
+            return numbers
+                .next()
+                .unwrap_or_else(|| Err(String::from("the file ends early")));
+        };
+
+        // Lamp to luminaire geometry and the tilt table, which don't apply here.
+        if tilt == "INCLUDE" {
+            next()?;
+            let count = next()? as usize;
+            for _ in 0..count.saturating_mul(2) {
+                next()?;
+            }
+        } else if tilt != "NONE" {
+            return Err(String::from("tilt files aren't supported"));
+        }
+
+        let _lamps = next()?;
+        let _lumens = next()?;
+        let multiplier = next()?;
+        let vertical_count = next()? as usize;
+        let horizontal_count = next()? as usize;
+        let photometric_type = next()? as i32;
+        // Units and the luminous opening's width, length and height.
+        for _ in 0..4 {
+            next()?;
+        }
+        let ballast = next()?;
+        let ballast_lamp = next()?;
+        let _watts = next()?;
+
+        if photometric_type != 1 {
+            return Err(String::from("only type C photometry is supported"));
+        }
+        if vertical_count == 0 || horizontal_count == 0 {
+            return Err(String::from("the profile has no angles"));
+        }
+
+        // The counts come from the file, so the lists grow as values are
+        // actually read rather than being allocated up front.
+        let candela_count = vertical_count
+            .checked_mul(horizontal_count)
+            .ok_or("the profile has too many angles")?;
+        let mut vertical = Vec::new();
+        for _ in 0..vertical_count {
+            vertical.push(next()?);
+        }
+        let mut horizontal = Vec::new();
+        for _ in 0..horizontal_count {
+            horizontal.push(next()?);
+        }
+        let scale = multiplier * ballast * ballast_lamp;
+        let mut candela = Vec::new();
+        for _ in 0..candela_count {
+            candela.push(f32::max(next()? * scale, 0.0));
+        }
+
+        let brightest = candela.iter().cloned().fold(0.0, f32::max);
+        if brightest <= 0.0 {
+            return Err(String::from("the profile emits no light"));
+        }
+        for c in candela.iter_mut() {
+            *c /= brightest;
+        }
+
+        return Ok(IesProfile {
+            source: String::from(source),
+            vertical,
+            horizontal,
+            candela,
+        });
+    }
+
+    // Relative intensity towards the given angles in degrees, interpolated
+    // between the measured ones.
+    pub fn intensity(&self, vertical: f32, horizontal: f32) -> f32 {
+        let first = self.vertical[0];
+        let last = self.vertical[self.vertical.len() - 1];
+        if vertical < first || vertical > last {
+            return 0.0;
+        }
+
+        // Fold the horizontal angle into the measured range using the
+        // symmetry that range implies.
+        let mut h = horizontal.rem_euclid(360.0);
+        let range = self.horizontal[self.horizontal.len() - 1] as i32;
+        if (range == 90 || range == 180) && h > 180.0 {
+            h = 360.0 - h;
+        }
+        if range == 90 && h > 90.0 {
+            h = 180.0 - h;
+        }
+
+        let (v0, tv) = bracket(&self.vertical, vertical);
+        let (h0, th) = bracket(&self.horizontal, h);
+        let v1 = usize::min(v0 + 1, self.vertical.len() - 1);
+        let h1 = usize::min(h0 + 1, self.horizontal.len() - 1);
+
+        let n = self.vertical.len();
+        let at = |h: usize, v: usize| self.candela[h * n + v];
+        let low = at(h0, v0) * (1.0 - tv) + at(h0, v1) * tv;
+        let high = at(h1, v0) * (1.0 - tv) + at(h1, v1) * tv;
+        return low * (1.0 - th) + high * th;
+    }
+
+    // Relative intensity towards direction, for a fixture pointing down axis
+    // with its 0 degree horizontal plane towards reference.
+    pub fn intensity_towards(&self, direction: Vec3, axis: Vec3, reference: Vec3) -> f32 {
+        let d = unit_vector(direction);
+        let vertical = f32::acos(clamp(dot(d, axis), -1.0, 1.0)) * 180.0 / PI;
+
+        let side = cross(axis, reference);
+        let horizontal = f32::atan2(dot(d, side), dot(d, reference)) * 180.0 / PI;
+        return self.intensity(vertical, horizontal);
+    }
+}
+
+// Index of the measured angle at or below angle and how far angle is towards
+// the next one.
+fn bracket(angles: &[f32], angle: f32) -> (usize, f32) {
+    let i = angles
+        .partition_point(|a| *a <= angle)
+        .saturating_sub(1)
+        .min(angles.len() - 1);
+    if i + 1 >= angles.len() {
+        return (i, 0.0);
+    }
+    let span = angles[i + 1] - angles[i];
+    if span <= 0.0 {
+        return (i, 0.0);
+    }
+    return (i, clamp((angle - angles[i]) / span, 0.0, 1.0));
+}
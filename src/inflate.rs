@@ -0,0 +1,278 @@
+// Decoder for zlib wrapped DEFLATE streams (RFC 1950 and 1951), enough for
+// the ZIP compressed scanlines of OpenEXR files.
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn bits(&mut self, count: u32) -> Result<u32, String> {
+        let mut value = 0;
+        for i in 0..count {
+            let byte = *self.data.get(self.pos).ok_or("truncated deflate stream")?;
+            value |= (((byte >> self.bit) & 1) as u32) << i;
+            self.bit += 1;
+            if self.bit == 8 {
+                self.bit = 0;
+                self.pos += 1;
+            }
+        }
+        return Ok(value);
+    }
+
+    fn align(&mut self) {
+        if self.bit != 0 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+    }
+}
+
+// Canonical Huffman code, decoded one bit at a time.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; 16];
+        for i in 1..16 {
+            offsets[i] = offsets[i - 1] + counts[i - 1];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+
+        return Self { counts, symbols };
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, String> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for length in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        return Err(String::from("invalid huffman code"));
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+pub fn zlib_decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() < 2 || data[0] & 0x0f != 8 {
+        return Err(String::from("not a zlib stream"));
+    }
+
+    let mut reader = BitReader {
+        data: &data[2..],
+        pos: 0,
+        bit: 0,
+    };
+    let mut out = Vec::new();
+
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align();
+                let start = reader.pos;
+                let header = reader
+                    .data
+                    .get(start..start + 4)
+                    .ok_or("truncated deflate stream")?;
+                let length = u16::from_le_bytes([header[0], header[1]]) as usize;
+                let block = reader
+                    .data
+                    .get(start + 4..start + 4 + length)
+                    .ok_or("truncated deflate stream")?;
+                out.extend_from_slice(block);
+                reader.pos = start + 4 + length;
+            }
+            1 => {
+                let mut lengths = [0u8; 288];
+                for (i, length) in lengths.iter_mut().enumerate() {
+                    *length = match i {
+                        0..=143 => 8,
+                        144..=255 => 9,
+                        256..=279 => 7,
+                        _ => 8,
+                    };
+                }
+                let literals = Huffman::new(&lengths);
+                let distances = Huffman::new(&[5u8; 30]);
+                inflate_block(&mut reader, &literals, &distances, &mut out)?;
+            }
+            2 => {
+                let (literals, distances) = read_dynamic_tables(&mut reader)?;
+                inflate_block(&mut reader, &literals, &distances, &mut out)?;
+            }
+            _ => return Err(String::from("invalid deflate block type")),
+        }
+
+        if last {
+            break;
+        }
+    }
+
+    return Ok(out);
+}
+
+fn read_dynamic_tables(reader: &mut BitReader) -> Result<(Huffman, Huffman), String> {
+    const ORDER: [usize; 19] = [
+        16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+    ];
+
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_count = reader.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for &i in ORDER.iter().take(code_count) {
+        code_lengths[i] = reader.bits(3)? as u8;
+    }
+    let codes = Huffman::new(&code_lengths);
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let symbol = codes.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths.last().ok_or("repeat without a previous length")?;
+                (previous, 3 + reader.bits(2)?)
+            }
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        for _ in 0..repeat {
+            lengths.push(value);
+        }
+    }
+    if lengths.len() != literal_count + distance_count {
+        return Err(String::from("code lengths overflow the tables"));
+    }
+
+    return Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ));
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    literals: &Huffman,
+    distances: &Huffman,
+    out: &mut Vec<u8>,
+) -> Result<(), String> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+        if symbol < 256 {
+            out.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+
+        let i = symbol - 257;
+        if i >= LENGTH_BASE.len() {
+            return Err(String::from("invalid length symbol"));
+        }
+        let length = LENGTH_BASE[i] as usize + reader.bits(LENGTH_EXTRA[i] as u32)? as usize;
+
+        let d = distances.decode(reader)? as usize;
+        if d >= DIST_BASE.len() {
+            return Err(String::from("invalid distance symbol"));
+        }
+        let distance = DIST_BASE[d] as usize + reader.bits(DIST_EXTRA[d] as u32)? as usize;
+        if distance > out.len() {
+            return Err(String::from("distance reaches before the start"));
+        }
+
+        // Copies byte by byte since the match may overlap what it produces.
+        let start = out.len() - distance;
+        for k in 0..length {
+            out.push(out[start + k]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stored_block() {
+        let data = [
+            0x78, 0x01, 0x01, 3, 0, 0xfc, 0xff, b'a', b'b', b'c', 0, 0, 0, 0,
+        ];
+        assert_eq!(zlib_decompress(&data).unwrap(), b"abc");
+    }
+
+    #[test]
+    fn fixed_huffman_block() {
+        let data = [120, 218, 203, 72, 205, 201, 201, 7, 0, 6, 44, 2, 21];
+        assert_eq!(zlib_decompress(&data).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn fixed_huffman_block_with_matches() {
+        let data = [120, 218, 75, 76, 74, 78, 68, 69, 0, 65, 124, 6, 229];
+        assert_eq!(zlib_decompress(&data).unwrap(), b"abcabcabcabcabcabc");
+    }
+
+    #[test]
+    fn dynamic_huffman_block() {
+        let data = [
+            120, 218, 29, 136, 193, 17, 0, 48, 12, 64, 102, 37, 246, 159, 161, 105, 30, 238, 32, 3,
+            242, 89, 9, 38, 219, 214, 251, 169, 225, 244, 0, 57, 140, 15, 81,
+        ];
+        assert_eq!(
+            zlib_decompress(&data).unwrap(),
+            b"bacaabaaabacaadaacdbdbaabbcaabadbbbdabcd"
+        );
+    }
+
+    #[test]
+    fn bad_streams_are_rejected() {
+        assert!(zlib_decompress(&[]).is_err());
+        assert!(zlib_decompress(&[0x79, 0x01]).is_err());
+        assert!(zlib_decompress(&[0x78, 0x01, 0x01, 9, 0, 0xf6, 0xff, b'a']).is_err());
+        assert!(zlib_decompress(&[120, 218, 203, 72, 205]).is_err());
+    }
+}
@@ -0,0 +1,89 @@
+use crate::animation::Track;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::math::quat::{AxisAngle, Quat};
+use crate::math::vec3::Vec3;
+use crate::ray::Ray;
+
+use std::sync::Arc;
+
+// Moves and rotates an object over time. The transform is evaluated at each
+// ray's time, so objects moving during the shutter are motion blurred.
+pub struct Keyframed {
+    object: Box<dyn Hittable>,
+    pivot: Vec3,
+    translate: Track<Vec3>,
+    rotate: Track<AxisAngle>,
+    mat: Option<Arc<dyn Material>>,
+}
+
+impl Keyframed {
+    pub fn new(
+        object: Box<dyn Hittable>,
+        pivot: Vec3,
+        translate: Track<Vec3>,
+        rotate: Track<AxisAngle>,
+        mat: Option<Arc<dyn Material>>,
+    ) -> Self {
+        Self {
+            object,
+            pivot,
+            translate,
+            rotate,
+            mat,
+        }
+    }
+
+    fn transform_at(&self, time: f32) -> (Vec3, Quat) {
+        let translation = self.translate.at(time).unwrap_or_default();
+        let rotation = self.rotate.at(time).unwrap_or_default().to_quat();
+        return (translation, rotation);
+    }
+}
+
+impl Hittable for Keyframed {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32, record: &mut HitRecord) -> bool {
+        let (translation, rotation) = self.transform_at(ray.time);
+        let inverse = rotation.conjugate();
+
+        // Trace the object in its own space, then bring the hit back.
+        let local_ray = Ray::with_time(
+            inverse.rotate(ray.origin - translation - self.pivot) + self.pivot,
+            inverse.rotate(ray.direction),
+            ray.time,
+        );
+        // The object tests hits against its own material's opacity, so hits
+        // on a material put in its place get tested again, looking further
+        // along the ray when they don't count.
+        let mut t_min = t_min;
+        loop {
+            if !self.object.hit(&local_ray, t_min, t_max, record) {
+                return false;
+            }
+
+            record.point = rotation.rotate(record.point - self.pivot) + self.pivot + translation;
+            record.normal = rotation.rotate(record.normal);
+            record.geometric_normal = rotation.rotate(record.geometric_normal);
+            record.dpdu = rotation.rotate(record.dpdu);
+            record.dpdv = rotation.rotate(record.dpdv);
+
+            match &self.mat {
+                Some(mat) => record.mat = Some(mat.clone()),
+                None => return true,
+            }
+            if record.alpha_test() {
+                return true;
+            }
+            t_min = record.t + 1e-4;
+        }
+    }
+
+    fn describe(&self) -> String {
+        let mut description = format!("Keyframed {}", self.object.describe());
+        if let Some(mat) = &self.mat {
+            description.push_str(" now ");
+            description.push_str(&mat.describe());
+        }
+        return description;
+    }
+}
@@ -0,0 +1,397 @@
+use crate::consts::*;
+use crate::hittable::{HitRecord, Hittable};
+use crate::ies::IesProfile;
+use crate::material::DiffuseLight;
+use crate::math::vec3::*;
+use crate::quad::Quad;
+use crate::ray::Ray;
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+// Light reaching a point from one light: the unit direction towards the light,
+// how far away it is and the radiance (irradiance for delta lights) arriving.
+// Delta lights have no pdf, for area lights it is over solid angle.
+pub struct LightSample {
+    pub direction: Vec3,
+    pub distance: f32,
+    pub radiance: Vec3,
+    pub pdf: Option<f32>,
+}
+
+// Lights sampled explicitly by the integrator. Delta lights can't be hit by
+// rays, area lights also put a shape with an emissive material in the world.
+pub trait Light {
+    fn sample(&self, point: Vec3, u: f32, v: f32) -> Option<LightSample>;
+    // Pdf of sample picking direction from point.
+    fn pdf(&self, _point: Vec3, _direction: Vec3) -> f32 {
+        return 0.0;
+    }
+    fn shape(&self) -> Option<Box<dyn Hittable>> {
+        return None;
+    }
+    // Whether a hit landed on this light's own shape.
+    fn is_hit(&self, _rec: &HitRecord) -> bool {
+        return false;
+    }
+    fn describe(&self) -> String;
+}
+
+fn describe_profile(profile: &Option<Arc<IesProfile>>) -> String {
+    return match profile {
+        Some(profile) => format!(" ies {}", profile.source),
+        None => String::new(),
+    };
+}
+
+// Shines in all directions, falling off with the squared distance. An IES
+// profile hangs from it pointing down -y.
+pub struct PointLight {
+    position: Vec3,
+    intensity: Vec3,
+    pub profile: Option<Arc<IesProfile>>,
+}
+
+impl PointLight {
+    pub fn new(position: Vec3, intensity: Vec3) -> Self {
+        Self {
+            position,
+            intensity,
+            profile: None,
+        }
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, point: Vec3, _u: f32, _v: f32) -> Option<LightSample> {
+        let to_light = self.position - point;
+        let distance_squared = to_light.length_squared();
+        if distance_squared <= 0.0 {
+            return None;
+        }
+
+        let direction = unit_vector(to_light);
+        let profile = match &self.profile {
+            Some(profile) => profile.intensity_towards(
+                -direction,
+                Vec3::new(0.0, -1.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+            ),
+            None => 1.0,
+        };
+
+        return Some(LightSample {
+            direction,
+            distance: f32::sqrt(distance_squared),
+            radiance: profile * self.intensity / distance_squared,
+            pdf: None,
+        });
+    }
+
+    fn describe(&self) -> String {
+        return format!(
+            "point {} intensity {}{}",
+            self.position,
+            self.intensity,
+            describe_profile(&self.profile)
+        );
+    }
+}
+
+// A point light limited to a cone, fading out between the inner and outer
+// half angles. An IES profile points along the cone.
+pub struct SpotLight {
+    position: Vec3,
+    direction: Vec3,
+    intensity: Vec3,
+    cos_inner: f32,
+    cos_outer: f32,
+    pub profile: Option<Arc<IesProfile>>,
+}
+
+impl SpotLight {
+    // Angles in degrees.
+    pub fn new(position: Vec3, direction: Vec3, intensity: Vec3, inner: f32, outer: f32) -> Self {
+        Self {
+            position,
+            direction: unit_vector(direction),
+            intensity,
+            cos_inner: f32::cos(degrees_to_radians(inner)),
+            cos_outer: f32::cos(degrees_to_radians(outer)),
+            profile: None,
+        }
+    }
+
+    fn falloff(&self, cos_theta: f32) -> f32 {
+        if cos_theta >= self.cos_inner {
+            return 1.0;
+        }
+        if cos_theta <= self.cos_outer {
+            return 0.0;
+        }
+        let t = (cos_theta - self.cos_outer) / (self.cos_inner - self.cos_outer);
+        return t * t * (3.0 - 2.0 * t);
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, point: Vec3, _u: f32, _v: f32) -> Option<LightSample> {
+        let to_light = self.position - point;
+        let distance_squared = to_light.length_squared();
+        if distance_squared <= 0.0 {
+            return None;
+        }
+
+        let direction = unit_vector(to_light);
+        let mut falloff = self.falloff(dot(-direction, self.direction));
+        if falloff <= 0.0 {
+            return None;
+        }
+        if let Some(profile) = &self.profile {
+            falloff *= profile.intensity_towards(
+                -direction,
+                self.direction,
+                perpendicular(self.direction),
+            );
+        }
+
+        return Some(LightSample {
+            direction,
+            distance: f32::sqrt(distance_squared),
+            radiance: falloff * self.intensity / distance_squared,
+            pdf: None,
+        });
+    }
+
+    fn describe(&self) -> String {
+        return format!(
+            "spot {} direction {} intensity {} cone {} {}{}",
+            self.position,
+            self.direction,
+            self.intensity,
+            f32::acos(self.cos_inner) * 180.0 / PI,
+            f32::acos(self.cos_outer) * 180.0 / PI,
+            describe_profile(&self.profile)
+        );
+    }
+}
+
+// Parallel light from infinitely far away, like a sun without a disk.
+pub struct DirectionalLight {
+    // Direction the light travels in.
+    direction: Vec3,
+    irradiance: Vec3,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vec3, irradiance: Vec3) -> Self {
+        Self {
+            direction: unit_vector(direction),
+            irradiance,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _point: Vec3, _u: f32, _v: f32) -> Option<LightSample> {
+        return Some(LightSample {
+            direction: -self.direction,
+            distance: INFINITY,
+            radiance: self.irradiance,
+            pdf: None,
+        });
+    }
+
+    fn describe(&self) -> String {
+        return format!(
+            "directional {} irradiance {}",
+            self.direction, self.irradiance
+        );
+    }
+}
+
+// Any vector perpendicular to a unit vector.
+fn perpendicular(axis: Vec3) -> Vec3 {
+    let other = if f32::abs(axis.y) > 0.9 {
+        Vec3::new(1.0, 0.0, 0.0)
+    } else {
+        Vec3::new(0.0, 1.0, 0.0)
+    };
+    return unit_vector(cross(other, axis));
+}
+
+// Emissive parallelogram, lighting the side its normal faces. An IES profile
+// points along the normal with its 0 degree plane along u.
+pub struct QuadLight {
+    quad: Quad,
+    material: Arc<DiffuseLight>,
+}
+
+impl QuadLight {
+    pub fn new(
+        q: Vec3,
+        u: Vec3,
+        v: Vec3,
+        radiance: Vec3,
+        profile: Option<Arc<IesProfile>>,
+    ) -> Self {
+        let normal = unit_vector(cross(u, v));
+        let mut material = DiffuseLight::new(radiance);
+        if let Some(profile) = profile {
+            material.profile = Some((profile, normal, unit_vector(u)));
+        }
+        let material = Arc::new(material);
+
+        return Self {
+            quad: Quad::new(q, u, v, material.clone()),
+            material,
+        };
+    }
+}
+
+impl Light for QuadLight {
+    fn sample(&self, point: Vec3, u: f32, v: f32) -> Option<LightSample> {
+        let on_light = self.quad.q + u * self.quad.u + v * self.quad.v;
+        let to_light = on_light - point;
+        let distance_squared = to_light.length_squared();
+        let direction = unit_vector(to_light);
+
+        let cosine = dot(-direction, self.quad.normal());
+        if cosine <= 0.0 || distance_squared <= 0.0 {
+            return None;
+        }
+
+        return Some(LightSample {
+            direction,
+            distance: f32::sqrt(distance_squared),
+            radiance: self.material.radiance_towards(-direction),
+            pdf: Some(distance_squared / (cosine * self.quad.area())),
+        });
+    }
+
+    fn pdf(&self, point: Vec3, direction: Vec3) -> f32 {
+        let direction = unit_vector(direction);
+        let t = match self
+            .quad
+            .intersect(&Ray::new(point, direction), 0.001, INFINITY)
+        {
+            Some((t, _, _)) => t,
+            None => return 0.0,
+        };
+
+        let cosine = dot(-direction, self.quad.normal());
+        if cosine <= 0.0 {
+            return 0.0;
+        }
+        return t * t / (cosine * self.quad.area());
+    }
+
+    fn shape(&self) -> Option<Box<dyn Hittable>> {
+        return Some(Box::new(self.quad.clone()));
+    }
+
+    // The shape carries this light's own material, which tells its hits apart.
+    fn is_hit(&self, rec: &HitRecord) -> bool {
+        return match &rec.mat {
+            Some(mat) => std::ptr::addr_eq(Arc::as_ptr(mat), Arc::as_ptr(&self.material)),
+            None => false,
+        };
+    }
+
+    fn describe(&self) -> String {
+        return format!("quad light {}", self.quad.describe());
+    }
+}
+
+// Reads lights from a text file, one per line:
+//
+//   point x y z  r g b
+//   spot x y z  dx dy dz  r g b  inner_degrees outer_degrees
+//   directional dx dy dz  r g b
+//   quad x y z  ux uy uz  vx vy vz  r g b
+//
+// Colors are intensities, irradiance for directional lights and radiance for
+// quads. Spot and directional directions point the way the light travels.
+// Point, spot and quad lines can end in `ies FILE` to shape the light with a
+// photometric profile, relative to the lights file. # starts a comment.
+pub fn load(path: &str) -> Result<Vec<Arc<dyn Light>>, String> {
+    let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+
+    let mut lights: Vec<Arc<dyn Light>> = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.is_empty() {
+            continue;
+        }
+
+        let light =
+            parse_light(path, &words).map_err(|err| format!("line {}: {}", number + 1, err))?;
+        lights.push(light);
+    }
+    return Ok(lights);
+}
+
+fn parse_light(path: &str, words: &[&str]) -> Result<Arc<dyn Light>, String> {
+    let (words, profile) = match words {
+        [rest @ .., "ies", file] => {
+            let file = Path::new(path).with_file_name(file);
+            let file = file.to_string_lossy();
+            let profile = IesProfile::load(&file).map_err(|err| format!("{}: {}", file, err))?;
+            (rest, Some(Arc::new(profile)))
+        }
+        _ => (words, None),
+    };
+    if profile.is_some() && words[0] == "directional" {
+        return Err(String::from("directional lights can't have an IES profile"));
+    }
+
+    let numbers = words[1..]
+        .iter()
+        .map(|word| {
+            word.parse::<f32>()
+                .map_err(|err| format!("invalid number {}: {}", word, err))
+        })
+        .collect::<Result<Vec<f32>, String>>()?;
+    let vec3 = |i: usize| Vec3::new(numbers[i], numbers[i + 1], numbers[i + 2]);
+
+    let direction = match (words[0], numbers.len()) {
+        ("spot", 11) => vec3(3),
+        ("directional", 6) => vec3(0),
+        _ => Vec3::new(0.0, 0.0, 1.0),
+    };
+    if direction.near_zero() {
+        return Err(String::from("the light direction is zero"));
+    }
+
+    return match (words[0], numbers.len()) {
+        ("point", 6) => {
+            let mut light = PointLight::new(vec3(0), vec3(3));
+            light.profile = profile;
+            Ok(Arc::new(light))
+        }
+        ("spot", 11) => {
+            if numbers[9] > numbers[10] {
+                return Err(String::from("the inner cone is wider than the outer"));
+            }
+            let mut light = SpotLight::new(vec3(0), vec3(3), vec3(6), numbers[9], numbers[10]);
+            light.profile = profile;
+            Ok(Arc::new(light))
+        }
+        ("directional", 6) => Ok(Arc::new(DirectionalLight::new(vec3(0), vec3(3)))),
+        ("quad", 12) => {
+            if cross(vec3(3), vec3(6)).near_zero() {
+                return Err(String::from("the quad has no area"));
+            }
+            Ok(Arc::new(QuadLight::new(
+                vec3(0),
+                vec3(3),
+                vec3(6),
+                vec3(9),
+                profile,
+            )))
+        }
+        _ => Err(format!("unknown light '{}'", words.join(" "))),
+    };
+}
@@ -1,51 +1,288 @@
+mod animation;
+mod aperture;
 mod camera;
+mod camera_controller;
+mod checkpoint;
 mod color;
 mod consts;
+mod denoise;
+mod environment;
+mod film;
+mod filter;
+mod hdr;
 mod hittable;
 mod hittable_list;
+mod ies;
+mod inflate;
+mod keyframed;
+mod light;
 mod material;
 mod math;
+mod medium;
+mod microfacet;
+mod quad;
 mod ray;
+mod sampler;
+mod scene;
+mod sky;
+mod spectrum;
 mod sphere;
+mod texture;
+mod video;
 
-use crate::camera::Camera;
-use crate::color::*;
+use crate::animation::Animation;
+use crate::aperture::{ApertureMask, ApertureShape};
+use crate::camera::{Camera, Projection, Stereo};
+use crate::camera_controller::CameraController;
+use crate::color::luminance;
 use crate::consts::*;
+use crate::environment::{Environment, EnvironmentMap, Gradient};
+use crate::film::Film;
 use crate::hittable::*;
 use crate::hittable_list::*;
 use crate::material::*;
-use crate::math::vec3::Vec3;
+use crate::math::vec3::{unit_vector, Vec3};
+use crate::medium::Medium;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use crate::sky::PhysicalSky;
+use crate::spectrum::{at_wavelength, sample_wavelength, wavelength_to_rgb};
 use crate::sphere::*;
+use crate::video::FrameWriter;
 use crate::KeyboardKey::*;
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use raylib::prelude::*;
 
 const WINDOW_WIDTH: usize = 800;
 const WINDOW_HEIGHT: usize = 800;
 
-fn ray_color(ray: &Ray, world: &dyn Hittable, depth: i32) -> Vec3 {
-    if depth <= 0 {
-        return Vec3::new(0.0, 0.0, 0.0);
-    }
+const CHECKPOINT_PATH: &str = "render.ckpt";
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(60);
 
-    let mut rec: HitRecord = Default::default();
-    if world.hit(ray, 0.001, INFINITY, &mut rec) {
-        let mut scattered: Ray = Default::default();
-        let mut attenuation: Vec3 = Default::default();
+const ADAPTIVE_MAX_SAMPLES: u32 = 256;
+const ADAPTIVE_THRESHOLD: f32 = 0.02;
+const HEATMAP_PATH: &str = "samples_heatmap.ppm";
+
+// Collisions a random walk through a medium gets before it counts as lost.
+const MAX_WALK_STEPS: u32 = 256;
+
+const RENDER_PATH: &str = "render.ppm";
+const DENOISED_PATH: &str = "render_denoised.ppm";
+
+// Path traced radiance along a ray. Surfaces that have a BSDF to evaluate
+// also sample the lights and the environment directly. The environment can be
+// found both ways, so those are weighted with multiple importance sampling.
+// Rays carrying a wavelength see every color as its spectrum's value there.
+fn ray_color(ray: &Ray, scene: &Scene, max_depth: i32, sampler: &mut dyn Sampler) -> Vec3 {
+    let mut color = Vec3::new(0.0, 0.0, 0.0);
+    let mut throughput = Vec3::new(1.0, 1.0, 1.0);
+    let mut ray = *ray;
+    // Pdf of the direction the last bounce picked, None for camera rays and
+    // bounces the lights couldn't have been sampled from, and where it left.
+    let mut scatter_pdf: Option<f32> = None;
+    let mut last_point = ray.origin;
+    let wavelength = ray.wavelength;
+
+    for _ in 0..max_depth {
+        let mut rec: HitRecord = Default::default();
+        if !scene.world.hit(&ray, 0.001, INFINITY, &mut rec) {
+            let weight = match scatter_pdf {
+                Some(pdf) => power_heuristic(pdf, scene.environment.pdf(ray.direction)),
+                None => 1.0,
+            };
+            let radiance = at_wavelength(scene.environment.eval(ray.direction), wavelength);
+            return color + weight * throughput * radiance;
+        }
+
+        // Hitting the inside of a surface with a medium under it, the ray
+        // walks through the medium first and may come back out elsewhere.
+        if !rec.front_face {
+            if let Some(medium) = rec.mat.as_ref().and_then(|mat| mat.medium()) {
+                let medium = medium.at_wavelength(wavelength);
+                match random_walk(&mut ray, &mut rec, &medium, scene, sampler) {
+                    Some(weight) => throughput = throughput * weight,
+                    None => return color,
+                }
+                scatter_pdf = None;
+                last_point = ray.origin;
+            }
+        }
+
+        let mat = match &rec.mat {
+            Some(mat) => mat.clone(),
+            None => {
+                let radiance = at_wavelength(scene.environment.eval(ray.direction), wavelength);
+                return color + throughput * radiance;
+            }
+        };
+        rec.wavelength = wavelength;
+        mat.perturb_normal(&mut rec);
 
-        if let Some(mat) = &rec.mat {
-            if mat.scatter(&ray, &rec, &mut attenuation, &mut scattered) {
-                return attenuation * ray_color(&scattered, world, depth - 1);
+        let emitted = at_wavelength(mat.emitted(&ray, &rec), wavelength);
+        if !emitted.near_zero() {
+            // Only the light whose shape was hit could have sampled this
+            // direction, not lights behind it, and emitters that aren't
+            // lights couldn't have been sampled at all.
+            let weight = match scatter_pdf {
+                Some(pdf) => {
+                    let light_pdf: f32 = scene
+                        .lights
+                        .iter()
+                        .filter(|light| light.is_hit(&rec))
+                        .map(|light| light.pdf(last_point, ray.direction))
+                        .sum();
+                    power_heuristic(pdf, light_pdf)
+                }
+                None => 1.0,
+            };
+            color = color + weight * throughput * emitted;
+        }
+
+        let wo = -unit_vector(ray.direction);
+        let (u, v) = sampler.get_2d();
+        if let Some((wi, radiance, light_pdf)) = scene.environment.sample(u, v) {
+            let f = at_wavelength(mat.eval(&rec, wi, wo), wavelength);
+            let radiance = at_wavelength(radiance, wavelength);
+            if !f.near_zero() && !occluded(scene, rec.offset_point(wi), wi, INFINITY, ray.time) {
+                let weight = power_heuristic(light_pdf, mat.pdf(&rec, wi, wo));
+                color = color + weight * throughput * f * radiance / light_pdf;
             }
-            return Vec3::new(0.0, 0.0, 0.0);
         }
+
+        // Delta lights can only be found this way, so only area lights are
+        // weighed against the bounce that could also have hit them.
+        for light in &scene.lights {
+            let (u, v) = sampler.get_2d();
+            let sample = match light.sample(rec.point, u, v) {
+                Some(sample) => sample,
+                None => continue,
+            };
+
+            let f = at_wavelength(mat.eval(&rec, sample.direction, wo), wavelength);
+            let radiance = at_wavelength(sample.radiance, wavelength);
+            if f.near_zero()
+                || occluded(
+                    scene,
+                    rec.offset_point(sample.direction),
+                    sample.direction,
+                    sample.distance,
+                    ray.time,
+                )
+            {
+                continue;
+            }
+
+            color = color
+                + match sample.pdf {
+                    Some(pdf) => {
+                        let weight = power_heuristic(pdf, mat.pdf(&rec, sample.direction, wo));
+                        weight * throughput * f * radiance / pdf
+                    }
+                    None => throughput * f * radiance,
+                };
+        }
+
+        let scattered = match mat.scatter(&ray, &rec, sampler) {
+            Some(scattered) => scattered,
+            None => return color,
+        };
+
+        scatter_pdf = if scattered.specular || scattered.pdf <= 0.0 {
+            None
+        } else {
+            Some(scattered.pdf)
+        };
+        throughput = throughput * at_wavelength(scattered.attenuation, wavelength);
+        last_point = rec.point;
+        ray = Ray::with_time(
+            rec.offset_point(scattered.direction),
+            scattered.direction,
+            ray.time,
+        );
+        ray.wavelength = wavelength;
     }
 
-    let unit_direction = ray.direction.unit_vector();
-    let t = 0.5 * (unit_direction.y + 1.0);
-    return (1.0 - t) * Vec3::new(1.0, 1.0, 1.0) + t * Vec3::new(0.5, 0.7, 1.0);
+    return color;
+}
+
+// Veach's power heuristic with beta = 2, the weight of a sample taken with
+// pdf_a when pdf_b could have produced it too.
+fn power_heuristic(pdf_a: f32, pdf_b: f32) -> f32 {
+    let a = pdf_a * pdf_a;
+    let b = pdf_b * pdf_b;
+    if a + b <= 0.0 {
+        return 0.0;
+    }
+    return a / (a + b);
+}
+
+// Follows a ray through a medium until it reaches a surface, scattering off
+// the medium on the way. Leaves the ray and its hit where it gets out, and
+// returns the throughput of the walk, or None if the walk gets lost.
+fn random_walk(
+    ray: &mut Ray,
+    rec: &mut HitRecord,
+    medium: &Medium,
+    scene: &Scene,
+    sampler: &mut dyn Sampler,
+) -> Option<Vec3> {
+    let mut throughput = Vec3::new(1.0, 1.0, 1.0);
+    for _ in 0..MAX_WALK_STEPS {
+        let (u, v) = sampler.get_2d();
+        let length = ray.direction.length();
+        let (distance, weight) = medium.sample_distance(u, v, rec.t * length, throughput);
+        throughput = throughput * weight;
+        if distance >= rec.t * length {
+            return Some(throughput);
+        }
+        if throughput.near_zero() {
+            return None;
+        }
+
+        let (u, v) = sampler.get_2d();
+        let direction = medium.sample_phase(ray.direction, u, v);
+        let wavelength = ray.wavelength;
+        *ray = Ray::with_time(ray.at(distance / length), direction, ray.time);
+        ray.wavelength = wavelength;
+
+        // Surfaces inside the medium end the walk as well, seen from the
+        // outside.
+        *rec = Default::default();
+        if !scene.world.hit(ray, 0.001, INFINITY, rec) {
+            return None;
+        }
+        if rec.front_face {
+            return Some(throughput);
+        }
+    }
+    return None;
+}
+
+// Whether anything blocks the way from point to distance along a unit direction.
+fn occluded(scene: &Scene, point: Vec3, direction: Vec3, distance: f32, time: f32) -> bool {
+    let shadow = Ray::with_time(point, direction, time);
+    let mut rec: HitRecord = Default::default();
+    return scene
+        .world
+        .hit(&shadow, 0.001, distance * (1.0 - 1e-4), &mut rec);
+}
+
+// Albedo and normal at the first hit, the guide buffers for the denoiser.
+fn first_hit_features(ray: &Ray, scene: &Scene) -> (Vec3, Vec3) {
+    let mut rec: HitRecord = Default::default();
+    if scene.world.hit(ray, 0.001, INFINITY, &mut rec) {
+        if let Some(mat) = rec.mat.clone() {
+            mat.perturb_normal(&mut rec);
+            return (mat.albedo(&rec), rec.normal);
+        }
+    }
+    return (
+        scene.environment.eval(ray.direction),
+        Vec3::new(0.0, 0.0, 0.0),
+    );
 }
 
 fn main() {
@@ -53,15 +290,180 @@ fn main() {
     let aspect_ratio = 3.0 / 2.0;
     let image_width = 1200 as i32;
     let image_height = (image_width as f32 / aspect_ratio) as i32;
-    let samples_per_pixel = 30;
+    let samples_per_pixel: u32 = 30;
     let max_depth = 50;
 
-    let (mut rl, thread) = raylib::init()
-        .size(image_width as i32, image_height as i32)
-        .title("ray tracing in one weekend")
-        .build();
+    // Adaptive sampling keeps tracing noisy pixels past samples_per_pixel and
+    // stops on pixels whose relative error is below the threshold.
+    let args: Vec<String> = std::env::args().collect();
+    let adaptive = args.iter().any(|arg| arg == "--adaptive");
+    let max_samples_per_pixel = if adaptive {
+        ADAPTIVE_MAX_SAMPLES
+    } else {
+        samples_per_pixel
+    };
+
+    // Film
+    let filter_name = arg_value(&args, "--filter").unwrap_or("box");
+    let filter = match filter::from_name(filter_name) {
+        Some(filter) => filter,
+        None => {
+            eprintln!(
+                "unknown filter {}, expected box, tent, gaussian, mitchell or lanczos",
+                filter_name
+            );
+            return;
+        }
+    };
+    let mut film = Film::new(image_width as usize, image_height as usize, filter);
 
-    let world = random_scene();
+    let denoise = args.iter().any(|arg| arg == "--denoise");
+
+    // Spectral mode traces a wavelength per sample, so dispersive glass
+    // splits light into colors.
+    let spectral = args.iter().any(|arg| arg == "--spectral");
+    let settings = RenderSettings {
+        max_depth,
+        spectral,
+    };
+
+    // Checkpoint
+    let resume = args.iter().any(|arg| arg == "--resume");
+    let checkpoint = if resume {
+        match checkpoint::load(CHECKPOINT_PATH, &mut film) {
+            Ok(checkpoint) => Some(checkpoint),
+            Err(err) => {
+                eprintln!("failed to read {}: {}", CHECKPOINT_PATH, err);
+                return;
+            }
+        }
+    } else {
+        None
+    };
+
+    let seed = match &checkpoint {
+        Some(checkpoint) => checkpoint.seed,
+        None => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0),
+    };
+    seed_random(seed);
+
+    let sampler_name = arg_value(&args, "--sampler").unwrap_or("independent");
+    let mut sampler = match sampler::from_name(sampler_name, samples_per_pixel, seed) {
+        Some(sampler) => sampler,
+        None => {
+            eprintln!(
+                "unknown sampler {}, expected independent, stratified, halton or sobol",
+                sampler_name
+            );
+            return;
+        }
+    };
+
+    // Environment
+    let intensity = match arg_f32(&args, "--env-intensity", 1.0) {
+        Ok(intensity) => intensity,
+        Err(err) => {
+            eprintln!("{}", err);
+            return;
+        }
+    };
+    let environment: Arc<dyn Environment> = match arg_value(&args, "--environment") {
+        None => Arc::new(Gradient {}),
+        Some("sky") => {
+            let mut values = [45.0, 0.0, 3.0, 0.3, 0.53];
+            let names = [
+                "--sun-elevation",
+                "--sun-azimuth",
+                "--turbidity",
+                "--ground-albedo",
+                "--sun-size",
+            ];
+            for (value, name) in values.iter_mut().zip(names) {
+                match arg_f32(&args, name, *value) {
+                    Ok(parsed) => *value = parsed,
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        return;
+                    }
+                }
+            }
+            let [elevation, azimuth, turbidity, ground_albedo, sun_size] = values;
+            if !(1.7..=10.0).contains(&turbidity) || sun_size <= 0.0 {
+                eprintln!("--turbidity needs to be within 1.7 and 10 and --sun-size positive");
+                return;
+            }
+
+            Arc::new(PhysicalSky::new(
+                elevation,
+                azimuth,
+                turbidity,
+                Vec3::new(ground_albedo, ground_albedo, ground_albedo),
+                sun_size,
+                intensity,
+            ))
+        }
+        Some(path) => {
+            let rotation = match arg_f32(&args, "--env-rotation", 0.0) {
+                Ok(rotation) => rotation,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    return;
+                }
+            };
+
+            match hdr::load(path) {
+                Ok(image) => Arc::new(EnvironmentMap::new(
+                    path,
+                    image,
+                    degrees_to_radians(rotation),
+                    intensity,
+                )),
+                Err(err) => {
+                    eprintln!("failed to load environment {}: {}", path, err);
+                    return;
+                }
+            }
+        }
+    };
+
+    // Lights
+    let lights = match arg_value(&args, "--lights") {
+        None => Vec::new(),
+        Some(path) => match light::load(path) {
+            Ok(lights) => lights,
+            Err(err) => {
+                eprintln!("failed to load lights {}: {}", path, err);
+                return;
+            }
+        },
+    };
+
+    // A still scene is an animation file seen at time 0, so its quads,
+    // objects, materials and camera carry over to the window.
+    let mut still = None;
+    let mut world = random_scene();
+    if let Some(path) = arg_value(&args, "--scene") {
+        let animation = match Animation::load(path) {
+            Ok(animation) => animation,
+            Err(err) => {
+                eprintln!("failed to load scene {}: {}", path, err);
+                return;
+            }
+        };
+        world = match animation.animate_world(world, 0.0) {
+            Ok(world) => world,
+            Err(err) => {
+                eprintln!("failed to build scene {}: {}", path, err);
+                return;
+            }
+        };
+        still = Some(animation);
+    }
+
+    let scene = Scene::new(world, environment.clone(), lights.clone());
 
     let lookfrom = Vec3::new(13.0, 2.0, 3.0);
     let lookat = Vec3::new(0.0, 0.0, 0.0);
@@ -70,7 +472,7 @@ fn main() {
     let aperture = 0.;
 
     // Camera
-    let cam = Camera::new(
+    let mut controller = CameraController::new(
         lookfrom,
         lookat,
         vup,
@@ -80,56 +482,550 @@ fn main() {
         dist_to_focus,
     );
 
-    let mut buffer =
-        vec![vec![Color::new(0, 0, 0, 0); (image_width + 1) as usize]; (image_height + 1) as usize];
+    if let Some(animation) = &still {
+        animation.apply_camera(&mut controller, 0.0);
+    }
+
+    let projection_name = arg_value(&args, "--projection").unwrap_or("perspective");
+    controller.projection = match Projection::from_name(projection_name) {
+        Some(projection) => projection,
+        None => {
+            eprintln!(
+                "unknown projection {}, expected perspective, orthographic, fisheye-equidistant, fisheye-equisolid or equirectangular",
+                projection_name
+            );
+            return;
+        }
+    };
+
+    let stereo_name = arg_value(&args, "--stereo").unwrap_or("mono");
+    let interocular = match arg_value(&args, "--interocular").map(|value| value.parse::<f32>()) {
+        None => 0.065,
+        Some(Ok(interocular)) => interocular,
+        Some(Err(err)) => {
+            eprintln!("invalid --interocular: {}", err);
+            return;
+        }
+    };
+    controller.stereo = match Stereo::from_name(stereo_name, interocular) {
+        Some(stereo) => stereo,
+        None => {
+            eprintln!(
+                "unknown stereo mode {}, expected mono, side-by-side or top-bottom",
+                stereo_name
+            );
+            return;
+        }
+    };
 
-    let mut rendered = false;
+    // Lens
+    if let Some(path) = arg_value(&args, "--aperture-mask") {
+        match load_aperture_mask(path) {
+            Ok(mask) => controller.aperture_shape = ApertureShape::Mask(Arc::new(mask)),
+            Err(err) => {
+                eprintln!("failed to load aperture mask {}: {}", path, err);
+                return;
+            }
+        }
+    } else if let Some(blades) = arg_value(&args, "--aperture-blades") {
+        let rotation = arg_value(&args, "--aperture-rotation").unwrap_or("0");
+        match (blades.parse::<u32>(), rotation.parse::<f32>()) {
+            (Ok(blades), Ok(rotation)) if blades >= 3 => {
+                controller.aperture_shape = ApertureShape::Polygon {
+                    blades,
+                    rotation: degrees_to_radians(rotation),
+                };
+            }
+            _ => {
+                eprintln!(
+                    "--aperture-blades needs at least 3 blades and --aperture-rotation degrees"
+                );
+                return;
+            }
+        }
+    }
+
+    for (name, value) in [
+        ("--cat-eye", &mut controller.cat_eye),
+        ("--tilt", &mut controller.tilt),
+        ("--swing", &mut controller.swing),
+    ] {
+        if let Some(text) = arg_value(&args, name) {
+            match text.parse::<f32>() {
+                Ok(parsed) => *value = parsed,
+                Err(err) => {
+                    eprintln!("invalid {}: {}", name, err);
+                    return;
+                }
+            }
+        }
+    }
+
+    // Animations render a numbered image sequence without opening a window.
+    if let Some(path) = arg_value(&args, "--animation") {
+        if resume {
+            eprintln!("--resume can't be used with --animation");
+            return;
+        }
+        if still.is_some() {
+            eprintln!("--scene can't be used with --animation");
+            return;
+        }
+
+        let animation = match Animation::load(path) {
+            Ok(animation) => animation,
+            Err(err) => {
+                eprintln!("failed to load animation {}: {}", path, err);
+                return;
+            }
+        };
+
+        let (first_frame, last_frame) = match arg_value(&args, "--frames") {
+            None => (animation.first_frame, animation.last_frame),
+            Some(range) => match parse_frame_range(range) {
+                Some(range) => range,
+                None => {
+                    eprintln!("--frames expects FIRST..LAST, got {}", range);
+                    return;
+                }
+            },
+        };
+
+        // Fraction of the frame interval the shutter stays open, 0.5 being
+        // the classic 180 degree shutter.
+        let shutter = match arg_value(&args, "--shutter").map(|value| value.parse::<f32>()) {
+            None => 0.5,
+            Some(Ok(shutter)) if shutter >= 0.0 => shutter,
+            _ => {
+                eprintln!("--shutter expects a non-negative fraction of a frame");
+                return;
+            }
+        };
+
+        // Besides the PPM frames, the sequence can be assembled into a GIF and
+        // streamed as Y4M or raw RGB24, where "-" is stdout.
+        let mut writers: Vec<(&str, Box<dyn FrameWriter>)> = Vec::new();
+        for format in ["gif", "y4m", "raw"] {
+            if let Some(path) = arg_value(&args, &format!("--{}", format)) {
+                if path == "-" && writers.iter().any(|(other, _)| *other == "-") {
+                    eprintln!("only one output can go to stdout");
+                    return;
+                }
+                match video::create(format, path, film.width, film.height, animation.fps) {
+                    Ok(writer) => writers.push((path, writer)),
+                    Err(err) => {
+                        eprintln!("failed to open {}: {}", path, err);
+                        return;
+                    }
+                }
+            }
+        }
+        if arg_value(&args, "--raw").is_some() {
+            eprintln!(
+                "raw frames are {}x{} rgb24 at {} fps",
+                film.width, film.height, animation.fps
+            );
+        }
+
+        for frame in first_frame..=last_frame {
+            let time = animation.frame_time(frame);
+
+            // Every frame starts from the same scene before it is animated.
+            seed_random(seed);
+            let scene = match animation.animate_world(random_scene(), time) {
+                Ok(world) => Scene::new(world, environment.clone(), lights.clone()),
+                Err(err) => {
+                    eprintln!("failed to animate {}: {}", path, err);
+                    return;
+                }
+            };
+
+            animation.apply_camera(&mut controller, time);
+            let mut cam = controller.camera();
+            cam.time0 = time;
+            cam.time1 = time + shutter / animation.fps;
+
+            film.reset();
+            let frame_seed = checkpoint::pass_seed(seed, frame);
+            for pass in 0..max_samples_per_pixel {
+                seed_random(checkpoint::pass_seed(frame_seed, pass));
+                let mut pass_sampled = false;
+
+                for y in 0..film.height {
+                    for x in 0..film.width {
+                        if adaptive && film.converged(x, y, samples_per_pixel, ADAPTIVE_THRESHOLD) {
+                            continue;
+                        }
+                        pass_sampled = true;
+                        trace_pixel(&mut film, sampler.as_mut(), &cam, &scene, x, y, settings);
+                    }
+                }
+
+                if !pass_sampled {
+                    break;
+                }
+            }
+
+            let image = film.resolve();
+            let frame_path = format!("frame_{:04}.ppm", frame);
+            if let Err(err) = film.save_ppm(&frame_path, &image) {
+                eprintln!("failed to write {}: {}", frame_path, err);
+                return;
+            }
+
+            if denoise {
+                let (albedo, normal) = film.features();
+                let clean = denoise::denoise(film.width, film.height, &image, &albedo, &normal);
+                let denoised_path = format!("frame_{:04}_denoised.ppm", frame);
+                if let Err(err) = film.save_ppm(&denoised_path, &clean) {
+                    eprintln!("failed to write {}: {}", denoised_path, err);
+                    return;
+                }
+            }
+
+            let rgb = film.rgb8(&image);
+            for (path, writer) in writers.iter_mut() {
+                if let Err(err) = writer.write_frame(&rgb) {
+                    eprintln!("failed to write {}: {}", path, err);
+                    return;
+                }
+            }
+
+            // Progress goes to stderr since stdout may be carrying frames.
+            eprintln!(
+                "wrote {} ({}/{})",
+                frame_path,
+                frame - first_frame + 1,
+                last_frame - first_frame + 1
+            );
+        }
+
+        for (path, writer) in writers.iter_mut() {
+            if let Err(err) = writer.finish() {
+                eprintln!("failed to write {}: {}", path, err);
+            }
+        }
+
+        return;
+    }
+
+    let mut cam = controller.camera();
+
+    let scene_hash = checkpoint::hash(&scene.describe());
+    // The camera is part of the settings, so the hash is taken again whenever
+    // it moves and the film starts over.
+    let settings_hash_for = |controller: &CameraController| -> u64 {
+        return checkpoint::hash(&format!(
+            "{}x{} spp {} depth {} adaptive {} spectral {} sampler {} filter {}\n{}",
+            image_width,
+            image_height,
+            samples_per_pixel,
+            max_depth,
+            adaptive,
+            spectral,
+            sampler_name,
+            filter_name,
+            controller.to_scene_string()
+        ));
+    };
+    let mut settings_hash = settings_hash_for(&controller);
+
+    let mut pass = 0;
+
+    if let Some(checkpoint) = checkpoint {
+        if checkpoint.scene_hash != scene_hash {
+            eprintln!("refusing to resume: the scene differs from the checkpoint");
+            return;
+        }
+        if checkpoint.settings_hash != settings_hash {
+            eprintln!("refusing to resume: the render settings differ from the checkpoint");
+            return;
+        }
+
+        pass = checkpoint.pass;
+        println!("resuming {} at pass {}", CHECKPOINT_PATH, pass);
+    }
+
+    let (mut rl, thread) = raylib::init()
+        .size(image_width as i32, image_height as i32)
+        .title("ray tracing in one weekend")
+        .build();
+
+    let mut pixels = vec![0u8; image_width as usize * image_height as usize * 4];
+
+    let image = Image::gen_image_color(image_width, image_height, Color::BLACK);
+    let mut texture = rl
+        .load_texture_from_image(&thread, &image)
+        .expect("failed to create the render texture");
+
+    // Rows are traced until the frame budget runs out so the window keeps
+    // handling input while the image refines one sample per pixel per pass.
+    let frame_budget = Duration::from_millis(16);
+    let mut row = 0;
+    let mut last_checkpoint = Instant::now();
+    let mut pass_sampled = false;
+    let mut show_heatmap = false;
+    let mut show_denoised = false;
+    let mut denoised: Option<Vec<Vec3>> = None;
 
     while !rl.window_should_close() {
-        if rl.is_key_pressed(KEY_SPACE) {
-            rendered = false;
+        let mut restart = rl.is_key_pressed(KEY_SPACE);
+        let mut redraw = false;
+
+        if rl.is_key_pressed(KEY_H) {
+            show_heatmap = !show_heatmap;
+            redraw = true;
+        }
+
+        if rl.is_key_pressed(KEY_N) {
+            show_denoised = !show_denoised;
+            redraw = true;
         }
 
-        if !rendered {
-            for y in 0..image_height as usize {
+        if controller.update(&rl) {
+            cam = controller.camera();
+            restart = true;
+        }
+
+        // Right click reports what is under the cursor, shift + right click
+        // also focuses the lens on it.
+        if rl.is_mouse_button_pressed(MouseButton::MOUSE_RIGHT_BUTTON) {
+            let mouse = rl.get_mouse_position();
+            let u = (mouse.x + 0.5) / image_width as f32;
+            let v = 1.0 - (mouse.y + 0.5) / image_height as f32;
+
+            match cam
+                .get_ray(u, v)
+                .and_then(|r| scene.world.pick(&r, 0.001, INFINITY))
+            {
+                Some((object, rec)) => {
+                    println!("object: {}", object.describe());
+                    println!("t: {} point: {} normal: {}", rec.t, rec.point, rec.normal);
+
+                    if rl.is_key_down(KEY_LEFT_SHIFT) {
+                        controller.focus_on(rec.point);
+                        println!("focus_dist: {}", controller.focus_dist);
+                        cam = controller.camera();
+                        restart = true;
+                    }
+                }
+                None => println!("nothing under the cursor"),
+            }
+        }
+
+        if restart {
+            settings_hash = settings_hash_for(&controller);
+            denoised = None;
+            film.reset();
+            pass = 0;
+            row = 0;
+        }
+
+        if pass < max_samples_per_pixel {
+            let frame_start = Instant::now();
+
+            while pass < max_samples_per_pixel && frame_start.elapsed() < frame_budget {
+                if row == 0 {
+                    seed_random(checkpoint::pass_seed(seed, pass));
+                    pass_sampled = false;
+                }
+
                 for x in 0..image_width as usize {
-                    let mut pixel_color = Vec3::new(0.0, 0.0, 0.0);
+                    if adaptive && film.converged(x, row, samples_per_pixel, ADAPTIVE_THRESHOLD) {
+                        continue;
+                    }
+                    pass_sampled = true;
+
+                    trace_pixel(&mut film, sampler.as_mut(), &cam, &scene, x, row, settings);
+                }
+
+                row += 1;
+                if row == image_height as usize {
+                    row = 0;
+                    pass += 1;
+                    denoised = None;
+
+                    // Every pixel converged, so the remaining passes would do nothing.
+                    if !pass_sampled {
+                        pass = max_samples_per_pixel;
+                    }
+
+                    if pass == max_samples_per_pixel && denoise {
+                        let image = film.resolve();
+                        let (albedo, normal) = film.features();
+                        let clean =
+                            denoise::denoise(film.width, film.height, &image, &albedo, &normal);
+
+                        for (path, image) in [(RENDER_PATH, &image), (DENOISED_PATH, &clean)] {
+                            if let Err(err) = film.save_ppm(path, image) {
+                                eprintln!("failed to write {}: {}", path, err);
+                            }
+                        }
+                        denoised = Some(clean);
+                    }
 
-                    for _ in 0..samples_per_pixel {
-                        let u = (x as f32 + random()) / image_width as f32;
-                        let v = (y as f32 + random()) / image_height as f32;
-                        let r = cam.get_ray(u, v);
-                        pixel_color = pixel_color + ray_color(&r, &world, max_depth);
+                    if pass == max_samples_per_pixel && adaptive {
+                        if let Err(err) = film.save_heatmap_ppm(HEATMAP_PATH, max_samples_per_pixel)
+                        {
+                            eprintln!("failed to write {}: {}", HEATMAP_PATH, err);
+                        }
                     }
 
-                    write_color(&mut pixel_color, samples_per_pixel);
-                    buffer[image_height as usize - y][x] = Color::new(
-                        pixel_color.x as u8,
-                        pixel_color.y as u8,
-                        pixel_color.z as u8,
-                        255,
-                    );
+                    if pass == max_samples_per_pixel
+                        || last_checkpoint.elapsed() >= CHECKPOINT_INTERVAL
+                    {
+                        if let Err(err) = checkpoint::save(
+                            CHECKPOINT_PATH,
+                            seed,
+                            pass,
+                            scene_hash,
+                            settings_hash,
+                            &film,
+                        ) {
+                            eprintln!("failed to write {}: {}", CHECKPOINT_PATH, err);
+                        }
+                        last_checkpoint = Instant::now();
+                    }
                 }
+            }
 
-                println!("{}% in Y.", (y as f32 / image_height as f32) * 100.0);
+            redraw = true;
+        }
+
+        if redraw {
+            if show_heatmap {
+                film.write_heatmap_rgba(&mut pixels, max_samples_per_pixel);
+            } else if show_denoised {
+                // Denoising takes a while, so it is only redone once per pass.
+                if denoised.is_none() {
+                    let (albedo, normal) = film.features();
+                    denoised = Some(denoise::denoise(
+                        film.width,
+                        film.height,
+                        &film.resolve(),
+                        &albedo,
+                        &normal,
+                    ));
+                }
+                if let Some(image) = &denoised {
+                    film.write_image_rgba(image, &mut pixels);
+                }
+            } else {
+                film.write_rgba(&mut pixels);
             }
-            println!("AEHO");
-            rendered = true;
+            texture.update_texture(&pixels);
         }
 
         let mut render = rl.begin_drawing(&thread);
         render.clear_background(Color::new(45, 42, 64, 255));
-        render.draw_text("AEHO HORA DO SHOW PORRA", 20, 20, 20, Color::BLACK);
+        render.draw_texture(&texture, 0, 0, Color::WHITE);
+        render.draw_text(
+            &format!("{}/{} spp", pass, max_samples_per_pixel),
+            20,
+            20,
+            20,
+            Color::BLACK,
+        );
+    }
+}
+
+// How every sample is traced.
+#[derive(Copy, Clone)]
+struct RenderSettings {
+    max_depth: i32,
+    spectral: bool,
+}
+
+// Traces one more sample of pixel (x, y) into the film.
+fn trace_pixel(
+    film: &mut Film,
+    sampler: &mut dyn Sampler,
+    cam: &Camera,
+    scene: &Scene,
+    x: usize,
+    y: usize,
+    settings: RenderSettings,
+) {
+    sampler.start_pixel_sample(x, y, film.sample_count(x, y));
+    let (px, py) = sampler.get_2d();
+    let lens = sampler.get_2d();
+    let time = sampler.get_1d();
+    // Spectral mode follows one wavelength per sample, and the radiance that
+    // comes back is a single value, turned into a color by where it was taken.
+    let (wavelength, wavelength_pdf) = if settings.spectral {
+        sample_wavelength(sampler.get_1d())
+    } else {
+        (0.0, 1.0)
+    };
+
+    let u = (x as f32 + px) / film.width as f32;
+    let v = (y as f32 + py) / film.height as f32;
 
-        for y in 0..image_height as usize {
-            for x in 0..image_width as usize {
-                let color = buffer[y as usize][x as usize];
-                render.draw_pixel(x as i32, y as i32, color);
+    // Pixels the projection doesn't reach stay black.
+    match cam.get_ray_sampled(u, v, lens, time) {
+        Some(mut r) => {
+            r.wavelength = wavelength;
+            let mut color = ray_color(&r, scene, settings.max_depth, sampler);
+            if settings.spectral {
+                color = wavelength_to_rgb(color.x, wavelength, wavelength_pdf);
             }
+            film.add_sample(x, y, px, py, color);
+
+            let (albedo, normal) = first_hit_features(&r, scene);
+            film.add_features(x, y, albedo, normal);
+        }
+        None => {
+            let black = Vec3::new(0.0, 0.0, 0.0);
+            film.add_sample(x, y, px, py, black);
+            film.add_features(x, y, black, black);
         }
     }
 }
 
+fn arg_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    let i = args.iter().position(|arg| arg == name)?;
+    return args.get(i + 1).map(|value| value.as_str());
+}
+
+// The number after a flag, or the default when the flag isn't given.
+fn arg_f32(args: &[String], name: &str, default: f32) -> Result<f32, String> {
+    return match arg_value(args, name) {
+        None => Ok(default),
+        Some(text) => text
+            .parse::<f32>()
+            .map_err(|err| format!("invalid {}: {}", name, err)),
+    };
+}
+
+// Parses an inclusive frame range like 10..20.
+fn parse_frame_range(range: &str) -> Option<(u32, u32)> {
+    let (first, last) = range.split_once("..")?;
+    let first = first.parse::<u32>().ok()?;
+    let last = last.parse::<u32>().ok()?;
+    if last < first {
+        return None;
+    }
+    return Some((first, last));
+}
+
+// Loads an image as an aperture mask, using its luminance as transmission.
+fn load_aperture_mask(path: &str) -> Result<ApertureMask, String> {
+    let image = Image::load_image(path)?;
+    let colors = image.get_image_data();
+    let values: Vec<f32> = colors
+        .iter()
+        .map(|c| luminance(Vec3::new(c.r as f32, c.g as f32, c.b as f32)) / 255.0)
+        .collect();
+
+    return ApertureMask::new(
+        path,
+        image.width() as usize,
+        image.height() as usize,
+        &values,
+    )
+    .ok_or_else(|| String::from("the mask is completely black"));
+}
+
 fn random_scene() -> HittableList {
     let mut world = HittableList::new();
 
@@ -1,17 +1,66 @@
+use crate::color::luminance;
+use crate::consts::*;
 use crate::hittable::*;
+use crate::ies::IesProfile;
 use crate::math::vec3::*;
+use crate::medium::Medium;
+use crate::microfacet::*;
 use crate::ray::*;
 
-use crate::consts::random;
+use crate::sampler::Sampler;
+use crate::spectrum::Ior;
+use crate::texture::*;
 
-pub trait Material {
+use std::sync::Arc;
+
+// Direction picked by Material::scatter, with the BSDF times the cosine over
+// the pdf as its attenuation. Specular directions come from a delta
+// distribution or one without a density, so their pdf means nothing and eval
+// and pdf never return them.
+pub struct ScatterRecord {
+    pub attenuation: Vec3,
+    pub direction: Vec3,
+    pub pdf: f32,
+    pub specular: bool,
+}
+
+pub trait Material: Send + Sync {
+    // None when the ray is absorbed.
     fn scatter(
         &self,
         r_in: &Ray,
         rec: &HitRecord,
-        attenuation: &mut Vec3,
-        scattered: &mut Ray,
-    ) -> bool;
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord>;
+    // BSDF times the cosine term for light arriving from direction wi and
+    // leaving towards wo, and the pdf of scatter picking wi when looking from
+    // wo. Both point away from the surface. Materials that only scatter in
+    // sharp lobes leave them at zero.
+    fn eval(&self, _rec: &HitRecord, _wi: Vec3, _wo: Vec3) -> Vec3 {
+        return Vec3::new(0.0, 0.0, 0.0);
+    }
+    fn pdf(&self, _rec: &HitRecord, _wi: Vec3, _wo: Vec3) -> f32 {
+        return 0.0;
+    }
+    // Light given off towards the ray that hit the surface.
+    fn emitted(&self, _r_in: &Ray, _rec: &HitRecord) -> Vec3 {
+        return Vec3::new(0.0, 0.0, 0.0);
+    }
+    // How much of the surface is there at the hit, from 0 where it's cut out
+    // to 1 where it's solid.
+    fn opacity(&self, _rec: &HitRecord) -> f32 {
+        return 1.0;
+    }
+    // Bends the shading normal of a hit before it gets shaded.
+    fn perturb_normal(&self, _rec: &mut HitRecord) {}
+    // Medium filling the inside of a closed surface, which rays that went
+    // in walk through before they reach the surface again.
+    fn medium(&self) -> Option<Medium> {
+        return None;
+    }
+    // Surface color at the hit, for the denoiser.
+    fn albedo(&self, rec: &HitRecord) -> Vec3;
+    fn describe(&self) -> String;
 }
 
 pub struct Lambertian {
@@ -29,18 +78,44 @@ impl Material for Lambertian {
         &self,
         _r_in: &Ray,
         rec: &HitRecord,
-        attenuation: &mut Vec3,
-        scattered: &mut Ray,
-    ) -> bool {
-        let mut scatter_direction = rec.normal + Vec3::random_unit_vector();
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let (u, v) = sampler.get_2d();
+        let mut scatter_direction = rec.normal + Vec3::uniform_sphere(u, v);
 
         if scatter_direction.near_zero() {
             scatter_direction = rec.normal;
         }
 
-        *scattered = Ray::new(rec.point, scatter_direction);
-        *attenuation = self.albedo;
-        return true;
+        let direction = unit_vector(scatter_direction);
+        return Some(ScatterRecord {
+            attenuation: self.albedo,
+            direction,
+            pdf: self.pdf(rec, direction, rec.normal),
+            specular: false,
+        });
+    }
+
+    fn eval(&self, rec: &HitRecord, wi: Vec3, _wo: Vec3) -> Vec3 {
+        let cosine = dot(rec.normal, unit_vector(wi));
+        if cosine <= 0.0 {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        return self.albedo * cosine / PI;
+    }
+
+    // Adding a point on the unit sphere to the normal gives cosine weighted
+    // directions.
+    fn pdf(&self, rec: &HitRecord, wi: Vec3, _wo: Vec3) -> f32 {
+        return f32::max(dot(rec.normal, unit_vector(wi)), 0.0) / PI;
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Vec3 {
+        return self.albedo;
+    }
+
+    fn describe(&self) -> String {
+        return format!("Lambertian albedo {}", self.albedo);
     }
 }
 
@@ -59,33 +134,50 @@ impl Metal {
 }
 
 impl Material for Metal {
+    // The fuzzed reflection has no density to speak of, so it counts as
+    // specular.
     fn scatter(
         &self,
         r_in: &Ray,
         rec: &HitRecord,
-        attenuation: &mut Vec3,
-        scattered: &mut Ray,
-    ) -> bool {
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
         let reflected = reflect(unit_vector(r_in.direction), rec.normal);
 
-        *scattered = Ray::new(
-            rec.point,
-            reflected + self.fuzz * Vec3::random_in_unit_sphere(),
-        );
-        *attenuation = self.albedo;
+        let (u, v) = sampler.get_2d();
+        let w = sampler.get_1d();
+        let direction = reflected + self.fuzz * Vec3::uniform_ball(u, v, w);
+        if dot(direction, rec.normal) <= 0.0 {
+            return None;
+        }
 
-        return dot(scattered.direction, rec.normal) > 0.0;
+        return Some(ScatterRecord {
+            attenuation: self.albedo,
+            direction: unit_vector(direction),
+            pdf: 0.0,
+            specular: true,
+        });
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Vec3 {
+        return self.albedo;
+    }
+
+    fn describe(&self) -> String {
+        return format!("Metal albedo {} fuzz {}", self.albedo, self.fuzz);
     }
 }
 
+// Smooth glass. Its index is constant unless ior is set to one that varies
+// with wavelength, which disperses rays that carry one.
 pub struct Dielectric {
-    ir: f32,
+    pub ior: Ior,
 }
 
 impl Dielectric {
     pub fn new(index_of_refraction: f32) -> Self {
         Self {
-            ir: index_of_refraction,
+            ior: Ior::Constant(index_of_refraction),
         }
     }
 }
@@ -101,15 +193,10 @@ impl Material for Dielectric {
         &self,
         r_in: &Ray,
         rec: &HitRecord,
-        attenuation: &mut Vec3,
-        scattered: &mut Ray,
-    ) -> bool {
-        *attenuation = Vec3::new(1.0, 1.0, 1.0);
-        let refraction_ratio = if rec.front_face {
-            1.0 / self.ir
-        } else {
-            self.ir
-        };
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let ir = self.ior.at(r_in.wavelength);
+        let refraction_ratio = if rec.front_face { 1.0 / ir } else { ir };
 
         let unit_direction = unit_vector(r_in.direction);
 
@@ -117,15 +204,972 @@ impl Material for Dielectric {
         let sin_theta = f32::sqrt(1.0 - cos_theta * cos_theta);
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
+        let choice = sampler.get_1d();
 
-        let direction = if cannot_refract && reflectance(cos_theta, refraction_ratio) > random() {
+        let direction = if cannot_refract && reflectance(cos_theta, refraction_ratio) > choice {
             reflect(unit_direction, rec.normal)
         } else {
             refract(unit_direction, rec.normal, refraction_ratio)
         };
 
-        *scattered = Ray::new(rec.point, direction);
+        return Some(ScatterRecord {
+            attenuation: Vec3::new(1.0, 1.0, 1.0),
+            direction: unit_vector(direction),
+            pdf: 0.0,
+            specular: true,
+        });
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Vec3 {
+        return Vec3::new(1.0, 1.0, 1.0);
+    }
+
+    fn describe(&self) -> String {
+        return match self.ior {
+            Ior::Constant(ir) => format!("Dielectric ir {}", ir),
+            _ => format!("Dielectric ir {} dispersive", self.ior.at(0.0)),
+        };
+    }
+}
+
+// Rough metal with a GGX microfacet distribution and the Fresnel reflectance
+// of a complex index of refraction.
+pub struct Conductor {
+    eta: Vec3,
+    k: Vec3,
+    roughness_u: f32,
+    roughness_v: f32,
+    distribution: Ggx,
+    // Degrees the direction of roughness_u is turned from the surface's u
+    // direction, about the normal.
+    pub rotation: f32,
+}
+
+impl Conductor {
+    pub fn new(eta: Vec3, k: Vec3, roughness: f32) -> Self {
+        return Conductor::anisotropic(eta, k, roughness, roughness);
+    }
+
+    // Brushed metal, with separate roughness along the surface's u and v
+    // directions.
+    pub fn anisotropic(eta: Vec3, k: Vec3, roughness_u: f32, roughness_v: f32) -> Self {
+        Self {
+            eta,
+            k,
+            roughness_u,
+            roughness_v,
+            distribution: Ggx::anisotropic(roughness_u, roughness_v),
+            rotation: 0.0,
+        }
+    }
+
+    // One of the metals in conductor_preset, by name.
+    pub fn preset(name: &str, roughness: f32) -> Option<Self> {
+        let (eta, k) = conductor_preset(name)?;
+        return Some(Conductor::new(eta, k, roughness));
+    }
+
+    // Local frame with x along the rotated u direction.
+    fn frame(&self, rec: &HitRecord) -> Frame {
+        let frame = Frame::from_normal_tangent(rec.normal, rec.dpdu);
+        if self.rotation == 0.0 {
+            return frame;
+        }
+        let angle = degrees_to_radians(self.rotation);
+        let tangent = f32::cos(angle) * frame.s + f32::sin(angle) * frame.t;
+        return Frame::from_normal_tangent(rec.normal, tangent);
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let frame = self.frame(rec);
+        let wo = frame.to_local(-unit_vector(r_in.direction));
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let (u, v) = sampler.get_2d();
+        let wi = self.distribution.sample_reflection(wo, u, v)?;
+
+        // With visible normal sampling most of the terms cancel.
+        let fresnel = fresnel_conductor(dot(wo, unit_vector(wo + wi)), self.eta, self.k);
+        return Some(ScatterRecord {
+            attenuation: fresnel * self.distribution.g(wo, wi) / self.distribution.g1(wo),
+            direction: frame.to_world(wi),
+            pdf: self.distribution.pdf_reflection(wo, wi),
+            specular: false,
+        });
+    }
+
+    fn eval(&self, rec: &HitRecord, wi: Vec3, wo: Vec3) -> Vec3 {
+        let frame = self.frame(rec);
+        let wo = frame.to_local(unit_vector(wo));
+        let wi = frame.to_local(unit_vector(wi));
+        let fresnel = fresnel_conductor(dot(wo, unit_vector(wo + wi)), self.eta, self.k);
+        return fresnel * self.distribution.eval_reflection(wo, wi);
+    }
+
+    fn pdf(&self, rec: &HitRecord, wi: Vec3, wo: Vec3) -> f32 {
+        let frame = self.frame(rec);
+        return self.distribution.pdf_reflection(
+            frame.to_local(unit_vector(wo)),
+            frame.to_local(unit_vector(wi)),
+        );
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Vec3 {
+        return fresnel_conductor(1.0, self.eta, self.k);
+    }
+
+    fn describe(&self) -> String {
+        if self.roughness_u == self.roughness_v {
+            return format!(
+                "Conductor eta {} k {} roughness {}",
+                self.eta, self.k, self.roughness_u
+            );
+        }
+        return format!(
+            "Conductor eta {} k {} roughness {} along u and {} along v rotated {}",
+            self.eta, self.k, self.roughness_u, self.roughness_v, self.rotation
+        );
+    }
+}
+
+// Rough glass with a GGX microfacet distribution, reflecting and refracting
+// through the sampled microfacet.
+pub struct RoughDielectric {
+    ir: f32,
+    roughness: f32,
+    distribution: Ggx,
+}
+
+impl RoughDielectric {
+    pub fn new(index_of_refraction: f32, roughness: f32) -> Self {
+        Self {
+            ir: index_of_refraction,
+            roughness,
+            distribution: Ggx::new(roughness),
+        }
+    }
+}
+
+// Frame of the normal facing the incoming ray and the ratio of the indices on
+// either side seen from there.
+fn dielectric_frame(rec: &HitRecord, ir: f32) -> (Frame, f32) {
+    let eta = if rec.front_face { ir } else { 1.0 / ir };
+    return (Frame::from_normal(rec.normal), eta);
+}
+
+impl Material for RoughDielectric {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let (frame, eta) = dielectric_frame(rec, self.ir);
+        let wo = frame.to_local(-unit_vector(r_in.direction));
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let (u, v) = sampler.get_2d();
+        let w = sampler.get_1d();
+        let wi = self.distribution.sample_dielectric(wo, eta, u, v, w)?;
+
+        // Choosing between the lobes by Fresnel leaves only the masking.
+        let g = self.distribution.g(wo, wi) / self.distribution.g1(wo);
+        return Some(ScatterRecord {
+            attenuation: Vec3::new(g, g, g),
+            direction: frame.to_world(wi),
+            pdf: self.distribution.pdf_dielectric(wo, wi, eta),
+            specular: false,
+        });
+    }
+
+    fn eval(&self, rec: &HitRecord, wi: Vec3, wo: Vec3) -> Vec3 {
+        let (frame, eta) = dielectric_frame(rec, self.ir);
+        let value = self.distribution.eval_dielectric(
+            frame.to_local(unit_vector(wo)),
+            frame.to_local(unit_vector(wi)),
+            eta,
+        );
+        return Vec3::new(value, value, value);
+    }
+
+    fn pdf(&self, rec: &HitRecord, wi: Vec3, wo: Vec3) -> f32 {
+        let (frame, eta) = dielectric_frame(rec, self.ir);
+        return self.distribution.pdf_dielectric(
+            frame.to_local(unit_vector(wo)),
+            frame.to_local(unit_vector(wi)),
+            eta,
+        );
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Vec3 {
+        return Vec3::new(1.0, 1.0, 1.0);
+    }
+
+    fn describe(&self) -> String {
+        return format!(
+            "RoughDielectric ir {} roughness {}",
+            self.ir, self.roughness
+        );
+    }
+}
+
+// Roughness of the clear coat, a thin polished layer on top.
+const CLEARCOAT_ROUGHNESS: f32 = 0.1;
+
+// One material covering most surfaces, after Burley 2012, "Physically-Based
+// Shading at Disney": a diffuse base with sheen, a GGX specular lobe tinted
+// towards the base color as it gets metallic, a clear coat and rough glass
+// transmission. Every parameter is a texture, scalar ones read its red
+// channel. All but the base color are set after construction.
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    // Reflectance of the dielectric specular lobe, 0.5 is 4 percent.
+    pub specular: Arc<dyn Texture>,
+    pub specular_tint: Arc<dyn Texture>,
+    pub sheen: Arc<dyn Texture>,
+    pub clearcoat: Arc<dyn Texture>,
+    pub transmission: Arc<dyn Texture>,
+    pub emission: Arc<dyn Texture>,
+    pub ir: f32,
+}
+
+// Parameters looked up at one hit.
+struct PrincipledHit {
+    base_color: Vec3,
+    metallic: f32,
+    roughness: f32,
+    specular: f32,
+    specular_tint: f32,
+    sheen: f32,
+    clearcoat: f32,
+    transmission: f32,
+}
+
+fn solid(value: f32) -> Arc<dyn Texture> {
+    return Arc::new(SolidColor::new(Vec3::new(value, value, value)));
+}
+
+fn lerp(a: Vec3, b: Vec3, t: f32) -> Vec3 {
+    return (1.0 - t) * a + t * b;
+}
+
+impl Principled {
+    pub fn new(base_color: Arc<dyn Texture>) -> Self {
+        Self {
+            base_color,
+            metallic: solid(0.0),
+            roughness: solid(0.5),
+            specular: solid(0.5),
+            specular_tint: solid(0.0),
+            sheen: solid(0.0),
+            clearcoat: solid(0.0),
+            transmission: solid(0.0),
+            emission: solid(0.0),
+            ir: 1.5,
+        }
+    }
+
+    fn at(&self, rec: &HitRecord) -> PrincipledHit {
+        let scalar = |texture: &Arc<dyn Texture>| -> f32 {
+            return clamp(texture.value(rec.u, rec.v, rec.point).x, 0.0, 1.0);
+        };
+        return PrincipledHit {
+            base_color: self.base_color.value(rec.u, rec.v, rec.point),
+            metallic: scalar(&self.metallic),
+            roughness: scalar(&self.roughness),
+            specular: scalar(&self.specular),
+            specular_tint: scalar(&self.specular_tint),
+            sheen: scalar(&self.sheen),
+            clearcoat: scalar(&self.clearcoat),
+            transmission: scalar(&self.transmission),
+        };
+    }
+
+    // Local frame facing the incoming ray, the index ratio across the surface
+    // and whether the ray is inside the glass, where only transmission applies.
+    fn local(&self, rec: &HitRecord, hit: &PrincipledHit) -> (Frame, f32, bool) {
+        let (frame, eta) = dielectric_frame(rec, self.ir);
+        return (frame, eta, !rec.front_face && hit.transmission > 0.0);
+    }
+
+    // Probabilities of sampling the diffuse, specular, clear coat and
+    // transmission lobes, roughly by how much each reflects.
+    fn lobe_weights(&self, hit: &PrincipledHit, wo: Vec3, inside: bool) -> [f32; 4] {
+        if inside {
+            return [0.0, 0.0, 0.0, 1.0];
+        }
+
+        let dielectric = 1.0 - hit.metallic;
+        let weights = [
+            dielectric * (1.0 - hit.transmission) * luminance(hit.base_color),
+            (1.0 - dielectric * hit.transmission)
+                * luminance(fresnel_schlick(wo.z, self.specular_color(hit))),
+            0.25 * hit.clearcoat * fresnel_schlick(wo.z, Vec3::new(0.04, 0.04, 0.04)).x,
+            dielectric * hit.transmission,
+        ];
+        let total: f32 = weights.iter().sum();
+        if total <= 0.0 {
+            return [0.0; 4];
+        }
+        return weights.map(|w| w / total);
+    }
+
+    // Reflectance at normal incidence of the specular lobe.
+    fn specular_color(&self, hit: &PrincipledHit) -> Vec3 {
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        let dielectric = 0.08 * hit.specular * lerp(white, tint(hit.base_color), hit.specular_tint);
+        return lerp(dielectric, hit.base_color, hit.metallic);
+    }
+
+    fn eval_local(&self, hit: &PrincipledHit, wo: Vec3, wi: Vec3, eta: f32, inside: bool) -> Vec3 {
+        let specular = Ggx::new(hit.roughness);
+        // Glass colors light by the square root of the base color at each
+        // crossing, so going in and out gives the base color.
+        let glass = Vec3::new(
+            f32::sqrt(f32::max(hit.base_color.x, 0.0)),
+            f32::sqrt(f32::max(hit.base_color.y, 0.0)),
+            f32::sqrt(f32::max(hit.base_color.z, 0.0)),
+        ) * specular.eval_dielectric(wo, wi, eta);
+        if inside {
+            return glass;
+        }
+
+        let dielectric = 1.0 - hit.metallic;
+        let mut value = dielectric * hit.transmission * glass;
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return value;
+        }
+
+        let h = unit_vector(wo + wi);
+        let cos_d = dot(wi, h);
+
+        // Burley's diffuse with its grazing retro-reflection, and sheen.
+        let fd90 = 0.5 + 2.0 * hit.roughness * cos_d * cos_d;
+        let fl = f32::powf(1.0 - wi.z, 5.0);
+        let fv = f32::powf(1.0 - wo.z, 5.0);
+        let diffuse = hit.base_color / PI * (1.0 + (fd90 - 1.0) * fl) * (1.0 + (fd90 - 1.0) * fv);
+        let sheen_color = lerp(Vec3::new(1.0, 1.0, 1.0), tint(hit.base_color), 0.5);
+        let sheen = hit.sheen * sheen_color * f32::powf(1.0 - cos_d, 5.0);
+        value = value + dielectric * (1.0 - hit.transmission) * (diffuse + sheen) * wi.z;
+
+        let fresnel = fresnel_schlick(dot(wo, h), self.specular_color(hit));
+        value = value
+            + (1.0 - dielectric * hit.transmission) * fresnel * specular.eval_reflection(wo, wi);
+
+        if hit.clearcoat > 0.0 {
+            let coat = Ggx::new(CLEARCOAT_ROUGHNESS);
+            let fresnel = fresnel_schlick(dot(wo, h), Vec3::new(0.04, 0.04, 0.04));
+            value = value + 0.25 * hit.clearcoat * fresnel * coat.eval_reflection(wo, wi);
+        }
+        return value;
+    }
+
+    fn pdf_local(&self, hit: &PrincipledHit, wo: Vec3, wi: Vec3, eta: f32, inside: bool) -> f32 {
+        let [diffuse, specular, clearcoat, transmission] = self.lobe_weights(hit, wo, inside);
+        let distribution = Ggx::new(hit.roughness);
+
+        let mut pdf = transmission * distribution.pdf_dielectric(wo, wi, eta);
+        if wi.z > 0.0 {
+            pdf += diffuse * wi.z / PI;
+            pdf += specular * distribution.pdf_reflection(wo, wi);
+            pdf += clearcoat * Ggx::new(CLEARCOAT_ROUGHNESS).pdf_reflection(wo, wi);
+        }
+        return pdf;
+    }
+}
+
+// Hue of a color with its luminance taken out.
+fn tint(color: Vec3) -> Vec3 {
+    let l = luminance(color);
+    if l <= 0.0 {
+        return Vec3::new(1.0, 1.0, 1.0);
+    }
+    return color / l;
+}
+
+impl Material for Principled {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let hit = self.at(rec);
+        let (frame, eta, inside) = self.local(rec, &hit);
+        let wo = frame.to_local(-unit_vector(r_in.direction));
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        // Every lobe takes the same sample dimensions.
+        let choice = sampler.get_1d();
+        let (u, v) = sampler.get_2d();
+        let w = sampler.get_1d();
+
+        let [diffuse, specular, clearcoat, _] = self.lobe_weights(&hit, wo, inside);
+        let wi = if choice < diffuse {
+            Some(unit_vector(
+                Vec3::new(0.0, 0.0, 1.0) + Vec3::uniform_sphere(u, v),
+            ))
+        } else if choice < diffuse + specular {
+            Ggx::new(hit.roughness).sample_reflection(wo, u, v)
+        } else if choice < diffuse + specular + clearcoat {
+            Ggx::new(CLEARCOAT_ROUGHNESS).sample_reflection(wo, u, v)
+        } else {
+            Ggx::new(hit.roughness).sample_dielectric(wo, eta, u, v, w)
+        };
+        let wi = match wi {
+            Some(wi) if !wi.near_zero() => wi,
+            _ => return None,
+        };
+
+        // Weighing by all the lobes that could have picked wi keeps the
+        // variance down where they overlap.
+        let pdf = self.pdf_local(&hit, wo, wi, eta, inside);
+        if pdf <= 0.0 {
+            return None;
+        }
+        return Some(ScatterRecord {
+            attenuation: self.eval_local(&hit, wo, wi, eta, inside) / pdf,
+            direction: frame.to_world(wi),
+            pdf,
+            specular: false,
+        });
+    }
+
+    fn eval(&self, rec: &HitRecord, wi: Vec3, wo: Vec3) -> Vec3 {
+        let hit = self.at(rec);
+        let (frame, eta, inside) = self.local(rec, &hit);
+        let wo = frame.to_local(unit_vector(wo));
+        if wo.z <= 0.0 {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        return self.eval_local(&hit, wo, frame.to_local(unit_vector(wi)), eta, inside);
+    }
+
+    fn pdf(&self, rec: &HitRecord, wi: Vec3, wo: Vec3) -> f32 {
+        let hit = self.at(rec);
+        let (frame, eta, inside) = self.local(rec, &hit);
+        let wo = frame.to_local(unit_vector(wo));
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        return self.pdf_local(&hit, wo, frame.to_local(unit_vector(wi)), eta, inside);
+    }
+
+    fn emitted(&self, _r_in: &Ray, rec: &HitRecord) -> Vec3 {
+        if !rec.front_face {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        return self.emission.value(rec.u, rec.v, rec.point);
+    }
 
-        return true;
+    fn albedo(&self, rec: &HitRecord) -> Vec3 {
+        return self.base_color.value(rec.u, rec.v, rec.point);
+    }
+
+    fn describe(&self) -> String {
+        return format!(
+            "Principled base {} metallic {} roughness {} specular {} specular tint {} sheen {} clearcoat {} transmission {} emission {} ir {}",
+            self.base_color.describe(),
+            self.metallic.describe(),
+            self.roughness.describe(),
+            self.specular.describe(),
+            self.specular_tint.describe(),
+            self.sheen.describe(),
+            self.clearcoat.describe(),
+            self.transmission.describe(),
+            self.emission.describe(),
+            self.ir
+        );
+    }
+}
+
+// Translucent material such as skin, wax or marble, lit by light that goes
+// under the surface and scatters through the medium inside. The surface
+// mirrors some light by Fresnel and lets the rest in, and out again, in
+// diffuse directions. The object has to be closed for the walk inside to
+// find its way out.
+pub struct Subsurface {
+    medium: Medium,
+    ir: f32,
+}
+
+impl Subsurface {
+    pub fn new(medium: Medium, ir: f32) -> Self {
+        Self { medium, ir }
+    }
+
+    // Share of the light coming from wo that goes through the surface.
+    fn transmittance(&self, rec: &HitRecord, wo: Vec3) -> f32 {
+        if !rec.front_face {
+            return 1.0;
+        }
+        return 1.0 - fresnel_dielectric(dot(rec.normal, unit_vector(wo)), self.ir);
+    }
+}
+
+impl Material for Subsurface {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let wo = -unit_vector(r_in.direction);
+        let transmittance = self.transmittance(rec, wo);
+        if sampler.get_1d() >= transmittance {
+            return Some(ScatterRecord {
+                attenuation: Vec3::new(1.0, 1.0, 1.0),
+                direction: reflect(-wo, rec.normal),
+                pdf: 0.0,
+                specular: true,
+            });
+        }
+
+        // Through the surface, cosine weighted about the normal on the other
+        // side.
+        let (u, v) = sampler.get_2d();
+        let mut direction = -rec.normal + Vec3::uniform_sphere(u, v);
+        if direction.near_zero() {
+            direction = -rec.normal;
+        }
+        let direction = unit_vector(direction);
+        return Some(ScatterRecord {
+            attenuation: Vec3::new(1.0, 1.0, 1.0),
+            direction,
+            pdf: self.pdf(rec, direction, wo),
+            specular: false,
+        });
+    }
+
+    fn eval(&self, rec: &HitRecord, wi: Vec3, wo: Vec3) -> Vec3 {
+        let f = self.pdf(rec, wi, wo);
+        return Vec3::new(f, f, f);
+    }
+
+    fn pdf(&self, rec: &HitRecord, wi: Vec3, wo: Vec3) -> f32 {
+        let cosine = dot(-rec.normal, unit_vector(wi));
+        if cosine <= 0.0 {
+            return 0.0;
+        }
+        return self.transmittance(rec, wo) * cosine / PI;
+    }
+
+    fn medium(&self) -> Option<Medium> {
+        return Some(self.medium);
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Vec3 {
+        return self.medium.albedo;
+    }
+
+    fn describe(&self) -> String {
+        return format!(
+            "Subsurface albedo {} mean free path {} anisotropy {} ir {}",
+            self.medium.albedo, self.medium.mean_free_path, self.medium.anisotropy, self.ir
+        );
+    }
+}
+
+// Smooth layer on top of another material.
+#[derive(Copy, Clone, Debug)]
+pub enum Coating {
+    // Clear varnish thick enough to reflect by plain Fresnel, like the clear
+    // coat of car paint.
+    Clear {
+        ir: f32,
+    },
+    // Film a few hundred nanometers thick, like soap or oil, whose reflections
+    // interfere into colors. Thickness is in nanometers, and the substrate
+    // index is what the film rests on as far as interference goes: 1 for a
+    // bubble in air, 1.33 for oil on water.
+    Film {
+        thickness: f32,
+        ir: f32,
+        substrate_ir: f32,
+    },
+}
+
+// Wavelengths standing in for the red, green and blue channels of rays that
+// carry no wavelength of their own.
+const CHANNEL_WAVELENGTHS: [f32; 3] = [650.0, 550.0, 450.0];
+
+impl Coating {
+    // Reflectance at the given wavelength, the same in every channel, or per
+    // channel for rays without a wavelength.
+    fn reflectance(&self, cos_i: f32, wavelength: f32) -> Vec3 {
+        return match *self {
+            Coating::Clear { ir } => {
+                let fresnel = fresnel_dielectric(cos_i, ir);
+                Vec3::new(fresnel, fresnel, fresnel)
+            }
+            Coating::Film {
+                thickness,
+                ir,
+                substrate_ir,
+            } => {
+                if wavelength > 0.0 {
+                    let fresnel = fresnel_thin_film(cos_i, wavelength, thickness, ir, substrate_ir);
+                    Vec3::new(fresnel, fresnel, fresnel)
+                } else {
+                    let [r, g, b] = CHANNEL_WAVELENGTHS.map(|wavelength| {
+                        fresnel_thin_film(cos_i, wavelength, thickness, ir, substrate_ir)
+                    });
+                    Vec3::new(r, g, b)
+                }
+            }
+        };
+    }
+}
+
+// A coating over a base material. The coating mirrors part of the light, and
+// what gets through lights the base, losing as much again on the way back out
+// unless the base lets it through to the other side.
+pub struct Coated {
+    base: Arc<dyn Material>,
+    coating: Coating,
+}
+
+impl Coated {
+    pub fn new(base: Arc<dyn Material>, coating: Coating) -> Self {
+        Self { base, coating }
+    }
+
+    fn transmittance(&self, rec: &HitRecord, wi: Vec3, wo: Vec3) -> Vec3 {
+        let one = Vec3::new(1.0, 1.0, 1.0);
+        let cos_o = dot(rec.normal, unit_vector(wo));
+        let cos_i = dot(rec.normal, unit_vector(wi));
+        let mut transmittance = one - self.coating.reflectance(cos_o, rec.wavelength);
+        if cos_i > 0.0 {
+            transmittance = transmittance * (one - self.coating.reflectance(cos_i, rec.wavelength));
+        }
+        return transmittance;
+    }
+
+    // Chance of taking the coating's reflection rather than the base's lobes.
+    fn coating_probability(&self, rec: &HitRecord, wo: Vec3) -> f32 {
+        let cos_o = dot(rec.normal, unit_vector(wo));
+        let reflectance = self.coating.reflectance(cos_o, rec.wavelength);
+        return (reflectance.x + reflectance.y + reflectance.z) / 3.0;
+    }
+}
+
+impl Material for Coated {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let wo = -unit_vector(r_in.direction);
+        let probability = self.coating_probability(rec, wo);
+        if sampler.get_1d() < probability {
+            return Some(ScatterRecord {
+                attenuation: self
+                    .coating
+                    .reflectance(dot(rec.normal, wo), rec.wavelength)
+                    / probability,
+                direction: reflect(-wo, rec.normal),
+                pdf: 0.0,
+                specular: true,
+            });
+        }
+
+        let mut scattered = self.base.scatter(r_in, rec, sampler)?;
+        scattered.attenuation = scattered.attenuation
+            * self.transmittance(rec, scattered.direction, wo)
+            / (1.0 - probability);
+        scattered.pdf *= 1.0 - probability;
+        return Some(scattered);
+    }
+
+    fn eval(&self, rec: &HitRecord, wi: Vec3, wo: Vec3) -> Vec3 {
+        return self.transmittance(rec, wi, wo) * self.base.eval(rec, wi, wo);
+    }
+
+    fn pdf(&self, rec: &HitRecord, wi: Vec3, wo: Vec3) -> f32 {
+        return (1.0 - self.coating_probability(rec, wo)) * self.base.pdf(rec, wi, wo);
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord) -> Vec3 {
+        return self.base.emitted(r_in, rec);
+    }
+
+    fn opacity(&self, rec: &HitRecord) -> f32 {
+        return self.base.opacity(rec);
+    }
+
+    fn perturb_normal(&self, rec: &mut HitRecord) {
+        self.base.perturb_normal(rec);
+    }
+
+    fn medium(&self) -> Option<Medium> {
+        return self.base.medium();
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Vec3 {
+        return self.base.albedo(rec);
+    }
+
+    fn describe(&self) -> String {
+        let coating = match self.coating {
+            Coating::Clear { ir } => format!("clear coat ir {}", ir),
+            Coating::Film {
+                thickness,
+                ir,
+                substrate_ir,
+            } => format!(
+                "thin film {} nm ir {} on ir {}",
+                thickness, ir, substrate_ir
+            ),
+        };
+        return format!("{} under {}", self.base.describe(), coating);
+    }
+}
+
+// Small scale relief drawn over a surface by bending its shading normal.
+pub enum Detail {
+    // Tangent space normals stored as colors, +x along u, +y along v and +z
+    // out of the surface.
+    NormalMap(Arc<dyn Texture>),
+    // Heights in the red channel, in units of scale.
+    BumpMap {
+        height: Arc<dyn Texture>,
+        scale: f32,
+    },
+}
+
+// Step in surface coordinates for the height's finite differences.
+const BUMP_DELTA: f32 = 1e-3;
+
+impl Detail {
+    // Perturbed normal on the outward side of the surface, from the outward
+    // geometric normal.
+    fn normal(&self, rec: &HitRecord, outward: Vec3) -> Vec3 {
+        return match self {
+            Detail::NormalMap(map) => {
+                let tangent = unit_vector(rec.dpdu - dot(rec.dpdu, outward) * outward);
+                let bitangent = cross(outward, tangent);
+                let c = 2.0 * map.value(rec.u, rec.v, rec.point) - 1.0;
+                unit_vector(c.x * tangent + c.y * bitangent + c.z * outward)
+            }
+            Detail::BumpMap { height, scale } => {
+                let h = |u: f32, v: f32| scale * height.value(u, v, rec.point).x;
+                let here = h(rec.u, rec.v);
+                let du = (h(rec.u + BUMP_DELTA, rec.v) - here) / BUMP_DELTA;
+                let dv = (h(rec.u, rec.v + BUMP_DELTA) - here) / BUMP_DELTA;
+                let normal = cross(rec.dpdu + du * outward, rec.dpdv + dv * outward);
+                if dot(normal, outward) < 0.0 {
+                    -unit_vector(normal)
+                } else {
+                    unit_vector(normal)
+                }
+            }
+        };
+    }
+}
+
+// A material with surface detail, which everything else it does sees in its
+// shading normal. Surfaces without surface coordinates stay flat.
+pub struct Detailed {
+    base: Arc<dyn Material>,
+    detail: Detail,
+}
+
+impl Detailed {
+    pub fn new(base: Arc<dyn Material>, detail: Detail) -> Self {
+        Self { base, detail }
+    }
+}
+
+impl Material for Detailed {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        return self.base.scatter(r_in, rec, sampler);
+    }
+
+    fn eval(&self, rec: &HitRecord, wi: Vec3, wo: Vec3) -> Vec3 {
+        return self.base.eval(rec, wi, wo);
+    }
+
+    fn pdf(&self, rec: &HitRecord, wi: Vec3, wo: Vec3) -> f32 {
+        return self.base.pdf(rec, wi, wo);
+    }
+
+    fn perturb_normal(&self, rec: &mut HitRecord) {
+        if !rec.dpdu.near_zero() && !rec.dpdv.near_zero() {
+            let outward = if rec.front_face {
+                rec.geometric_normal
+            } else {
+                -rec.geometric_normal
+            };
+            let normal = self.detail.normal(rec, outward);
+            rec.normal = if rec.front_face { normal } else { -normal };
+        }
+        self.base.perturb_normal(rec);
+    }
+
+    fn opacity(&self, rec: &HitRecord) -> f32 {
+        return self.base.opacity(rec);
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord) -> Vec3 {
+        return self.base.emitted(r_in, rec);
+    }
+
+    fn medium(&self) -> Option<Medium> {
+        return self.base.medium();
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Vec3 {
+        return self.base.albedo(rec);
+    }
+
+    fn describe(&self) -> String {
+        let detail = match &self.detail {
+            Detail::NormalMap(map) => format!("normal map {}", map.describe()),
+            Detail::BumpMap { height, scale } => {
+                format!("bump map {} scale {}", height.describe(), scale)
+            }
+        };
+        return format!("{} with {}", self.base.describe(), detail);
+    }
+}
+
+// A material with parts of it cut out by an opacity mask, read from the red
+// channel, like the leaves on a foliage card. Rays go through the cut out
+// parts without the surface being there at all, and through fractional
+// opacity as often as it is transparent.
+pub struct Cutout {
+    base: Arc<dyn Material>,
+    opacity: Arc<dyn Texture>,
+}
+
+impl Cutout {
+    pub fn new(base: Arc<dyn Material>, opacity: Arc<dyn Texture>) -> Self {
+        Self { base, opacity }
+    }
+}
+
+impl Material for Cutout {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        return self.base.scatter(r_in, rec, sampler);
+    }
+
+    fn eval(&self, rec: &HitRecord, wi: Vec3, wo: Vec3) -> Vec3 {
+        return self.base.eval(rec, wi, wo);
+    }
+
+    fn pdf(&self, rec: &HitRecord, wi: Vec3, wo: Vec3) -> f32 {
+        return self.base.pdf(rec, wi, wo);
+    }
+
+    fn opacity(&self, rec: &HitRecord) -> f32 {
+        let opacity = self.opacity.value(rec.u, rec.v, rec.point).x;
+        return opacity * self.base.opacity(rec);
+    }
+
+    fn perturb_normal(&self, rec: &mut HitRecord) {
+        self.base.perturb_normal(rec);
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord) -> Vec3 {
+        return self.base.emitted(r_in, rec);
+    }
+
+    fn medium(&self) -> Option<Medium> {
+        return self.base.medium();
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Vec3 {
+        return self.base.albedo(rec);
+    }
+
+    fn describe(&self) -> String {
+        return format!(
+            "{} with opacity {}",
+            self.base.describe(),
+            self.opacity.describe()
+        );
+    }
+}
+
+// Emits light from its front face and doesn't scatter any.
+pub struct DiffuseLight {
+    emit: Vec3,
+    // Photometric profile with the fixture's axis and 0 degree plane.
+    pub profile: Option<(Arc<IesProfile>, Vec3, Vec3)>,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Vec3) -> Self {
+        Self {
+            emit,
+            profile: None,
+        }
+    }
+
+    // Radiance leaving in the given direction, assuming it leaves the front.
+    pub fn radiance_towards(&self, direction: Vec3) -> Vec3 {
+        return match &self.profile {
+            Some((profile, axis, reference)) => {
+                profile.intensity_towards(direction, *axis, *reference) * self.emit
+            }
+            None => self.emit,
+        };
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        _rec: &HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        return None;
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord) -> Vec3 {
+        if !rec.front_face {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        return self.radiance_towards(-r_in.direction);
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Vec3 {
+        return Vec3::new(1.0, 1.0, 1.0);
+    }
+
+    fn describe(&self) -> String {
+        let mut description = format!("DiffuseLight emit {}", self.emit);
+        if let Some((profile, _, _)) = &self.profile {
+            description.push_str(" ies ");
+            description.push_str(&profile.source);
+        }
+        return description;
     }
 }
@@ -1 +1,2 @@
+pub mod quat;
 pub mod vec3;
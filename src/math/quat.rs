@@ -0,0 +1,136 @@
+use crate::consts::*;
+use crate::math::vec3::*;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Quat {
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Default for Quat {
+    fn default() -> Self {
+        return Quat::identity();
+    }
+}
+
+impl Quat {
+    pub fn identity() -> Self {
+        Self {
+            w: 1.0,
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        }
+    }
+
+    pub fn from_axis_angle(axis: Vec3, degrees: f32) -> Self {
+        let half = degrees_to_radians(degrees) / 2.0;
+        let a = unit_vector(axis) * f32::sin(half);
+        Self {
+            w: f32::cos(half),
+            x: a.x,
+            y: a.y,
+            z: a.z,
+        }
+    }
+
+    pub fn conjugate(&self) -> Quat {
+        Quat {
+            w: self.w,
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+        }
+    }
+
+    pub fn rotate(&self, v: Vec3) -> Vec3 {
+        let q = Vec3::new(self.x, self.y, self.z);
+        let t = 2.0 * cross(q, v);
+        return v + self.w * t + cross(q, t);
+    }
+
+    pub fn dot(&self, other: &Quat) -> f32 {
+        return self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z;
+    }
+
+    fn scale(&self, s: f32) -> Quat {
+        Quat {
+            w: self.w * s,
+            x: self.x * s,
+            y: self.y * s,
+            z: self.z * s,
+        }
+    }
+
+    fn add(&self, other: &Quat) -> Quat {
+        Quat {
+            w: self.w + other.w,
+            x: self.x + other.x,
+            y: self.y + other.y,
+            z: self.z + other.z,
+        }
+    }
+
+    fn normalized(&self) -> Quat {
+        return self.scale(1.0 / f32::sqrt(self.dot(self)));
+    }
+}
+
+// A rotation as it is keyed, which unlike a quaternion tells a full turn from
+// none and a turn one way from a turn the other way.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AxisAngle {
+    pub axis: Vec3,
+    pub degrees: f32,
+}
+
+impl Default for AxisAngle {
+    fn default() -> Self {
+        return AxisAngle::new(Vec3::new(0.0, 1.0, 0.0), 0.0);
+    }
+}
+
+impl AxisAngle {
+    pub fn new(axis: Vec3, degrees: f32) -> Self {
+        Self {
+            axis: unit_vector(axis),
+            degrees,
+        }
+    }
+
+    pub fn from_quat(q: Quat) -> Self {
+        let axis = Vec3::new(q.x, q.y, q.z);
+        if axis.near_zero() {
+            return AxisAngle::default();
+        }
+        let degrees = 2.0 * f32::acos(clamp(q.w, -1.0, 1.0)) * 180.0 / PI;
+        return AxisAngle::new(axis, degrees);
+    }
+
+    pub fn to_quat(self) -> Quat {
+        return Quat::from_axis_angle(self.axis, self.degrees);
+    }
+}
+
+// Spherical linear interpolation along the shorter arc.
+pub fn slerp(a: Quat, b: Quat, t: f32) -> Quat {
+    let mut b = b;
+    let mut cos_theta = a.dot(&b);
+    if cos_theta < 0.0 {
+        b = b.scale(-1.0);
+        cos_theta = -cos_theta;
+    }
+
+    // Nearly parallel rotations, where sin(theta) below would vanish.
+    if cos_theta > 0.9995 {
+        return a.scale(1.0 - t).add(&b.scale(t)).normalized();
+    }
+
+    let theta = f32::acos(cos_theta);
+    let sin_theta = f32::sin(theta);
+    return a
+        .scale(f32::sin((1.0 - t) * theta) / sin_theta)
+        .add(&b.scale(f32::sin(t * theta) / sin_theta));
+}
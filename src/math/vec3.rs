@@ -1,4 +1,5 @@
 use crate::consts::*;
+use std::fmt;
 use std::ops::{Add, Div, Mul, Neg, Sub};
 
 #[derive(Default, Debug, Copy, Clone, PartialEq)]
@@ -60,6 +61,35 @@ impl Vec3 {
         }
     }
 
+    // Concentric mapping of a point in [0, 1)^2 onto the unit disk in the xy plane.
+    pub fn concentric_disk(u: f32, v: f32) -> Self {
+        let a = 2.0 * u - 1.0;
+        let b = 2.0 * v - 1.0;
+        if a == 0.0 && b == 0.0 {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+
+        let (r, theta) = if f32::abs(a) > f32::abs(b) {
+            (a, PI / 4.0 * (b / a))
+        } else {
+            (b, PI / 2.0 - PI / 4.0 * (a / b))
+        };
+        return Vec3::new(r * f32::cos(theta), r * f32::sin(theta), 0.0);
+    }
+
+    // Maps a point in [0, 1)^2 uniformly onto the unit sphere's surface.
+    pub fn uniform_sphere(u: f32, v: f32) -> Self {
+        let z = 1.0 - 2.0 * u;
+        let r = f32::sqrt(f32::max(1.0 - z * z, 0.0));
+        let phi = 2.0 * PI * v;
+        return Vec3::new(r * f32::cos(phi), r * f32::sin(phi), z);
+    }
+
+    // Maps a point in [0, 1)^3 uniformly into the unit ball.
+    pub fn uniform_ball(u: f32, v: f32, w: f32) -> Self {
+        return f32::cbrt(w) * Vec3::uniform_sphere(u, v);
+    }
+
     pub fn random_unit_vector() -> Self {
         return unit_vector(Vec3::random_in_unit_sphere());
     }
@@ -108,6 +138,12 @@ pub fn cross(u: Vec3, v: Vec3) -> Vec3 {
     }
 }
 
+impl fmt::Display for Vec3 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({}, {}, {})", self.x, self.y, self.z)
+    }
+}
+
 impl Neg for Vec3 {
     type Output = Vec3;
     fn neg(self) -> Vec3 {
@@ -0,0 +1,135 @@
+use crate::consts::*;
+use crate::math::vec3::*;
+use crate::microfacet::Frame;
+use crate::spectrum::at_wavelength;
+
+// Homogeneous scattering medium, set by how far light travels between
+// collisions on average and the color it comes out with after many of them,
+// per channel.
+#[derive(Copy, Clone, Debug)]
+pub struct Medium {
+    pub mean_free_path: Vec3,
+    pub albedo: Vec3,
+    // Henyey-Greenstein asymmetry, from -1 (backwards) through 0 (isotropic)
+    // to 1 (forwards).
+    pub anisotropy: f32,
+}
+
+impl Medium {
+    pub fn new(mean_free_path: Vec3, albedo: Vec3) -> Self {
+        Self {
+            mean_free_path,
+            albedo,
+            anisotropy: 0.0,
+        }
+    }
+
+    // The medium as seen by a ray of the given wavelength.
+    pub fn at_wavelength(&self, wavelength: f32) -> Medium {
+        return Medium {
+            mean_free_path: at_wavelength(self.mean_free_path, wavelength),
+            albedo: at_wavelength(self.albedo, wavelength),
+            anisotropy: self.anisotropy,
+        };
+    }
+
+    fn extinction(&self) -> Vec3 {
+        let channel = |mean_free_path: f32| 1.0 / f32::max(mean_free_path, 1e-4);
+        return Vec3::new(
+            channel(self.mean_free_path.x),
+            channel(self.mean_free_path.y),
+            channel(self.mean_free_path.z),
+        );
+    }
+
+    // Albedo of a single collision that gives the albedo after many, fitted
+    // by Chiang et al. 2016, "Practical and Controllable Subsurface Scattering
+    // for Production Path Tracing".
+    fn single_scattering_albedo(&self) -> Vec3 {
+        let channel = |albedo: f32| -> f32 {
+            let a = clamp(albedo, 0.0, 1.0);
+            let s = 4.09712 + 4.20863 * a - f32::sqrt(9.59217 + 41.6808 * a + 17.7126 * a * a);
+            return clamp(1.0 - s * s, 0.0, 1.0);
+        };
+        return Vec3::new(
+            channel(self.albedo.x),
+            channel(self.albedo.y),
+            channel(self.albedo.z),
+        );
+    }
+
+    // Distance to the next collision along a ray that leaves the medium after
+    // max_distance, with the transmittance over the pdf, times the scattering
+    // coefficient for collisions. Distances at or past max_distance mean the
+    // ray got out. Each channel samples the distance as often as it carries
+    // throughput, so the walk follows the colors that still matter, and the
+    // pdf weighs all three.
+    pub fn sample_distance(
+        &self,
+        u: f32,
+        v: f32,
+        max_distance: f32,
+        throughput: Vec3,
+    ) -> (f32, Vec3) {
+        let extinction = self.extinction();
+        let total = throughput.x + throughput.y + throughput.z;
+        if total <= 0.0 {
+            return (max_distance, Vec3::new(0.0, 0.0, 0.0));
+        }
+        let chances = throughput / total;
+        let sigma = if u < chances.x {
+            extinction.x
+        } else if u < chances.x + chances.y {
+            extinction.y
+        } else {
+            extinction.z
+        };
+        let distance = -f32::ln(1.0 - v) / sigma;
+
+        let transmittance = |distance: f32| -> Vec3 {
+            return Vec3::new(
+                f32::exp(-extinction.x * distance),
+                f32::exp(-extinction.y * distance),
+                f32::exp(-extinction.z * distance),
+            );
+        };
+
+        if distance >= max_distance {
+            let tr = transmittance(max_distance);
+            let pdf = dot(chances, tr);
+            if pdf <= 0.0 {
+                return (max_distance, Vec3::new(0.0, 0.0, 0.0));
+            }
+            return (max_distance, tr / pdf);
+        }
+
+        let tr = transmittance(distance);
+        let density = extinction * tr;
+        let pdf = dot(chances, density);
+        if pdf <= 0.0 {
+            return (distance, Vec3::new(0.0, 0.0, 0.0));
+        }
+        return (distance, self.single_scattering_albedo() * density / pdf);
+    }
+
+    // New direction after a collision, sampled exactly from the phase function
+    // so its weight is one.
+    pub fn sample_phase(&self, direction: Vec3, u: f32, v: f32) -> Vec3 {
+        let g = self.anisotropy;
+        let cos_theta = if f32::abs(g) < 1e-3 {
+            1.0 - 2.0 * u
+        } else {
+            let square = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+            (1.0 + g * g - square * square) / (2.0 * g)
+        };
+        let sin_theta = f32::sqrt(f32::max(1.0 - cos_theta * cos_theta, 0.0));
+        let phi = 2.0 * PI * v;
+
+        let frame = Frame::from_normal(unit_vector(direction));
+        return unit_vector(frame.to_world(Vec3::new(
+            sin_theta * f32::cos(phi),
+            sin_theta * f32::sin(phi),
+            cos_theta,
+        )));
+    }
+}
@@ -0,0 +1,357 @@
+use crate::consts::*;
+use crate::math::vec3::*;
+
+// Orthonormal basis around a normal, for working in a local frame where the
+// normal is +z.
+#[derive(Copy, Clone)]
+pub struct Frame {
+    pub s: Vec3,
+    pub t: Vec3,
+    pub n: Vec3,
+}
+
+impl Frame {
+    pub fn from_normal(n: Vec3) -> Self {
+        let other = if f32::abs(n.x) > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let s = unit_vector(cross(other, n));
+        let t = cross(n, s);
+        return Self { s, t, n };
+    }
+
+    // Frame with s along the tangent, made perpendicular to the normal, or
+    // any frame if the tangent is along the normal.
+    pub fn from_normal_tangent(n: Vec3, tangent: Vec3) -> Self {
+        let s = tangent - dot(tangent, n) * n;
+        if s.near_zero() {
+            return Frame::from_normal(n);
+        }
+        let s = unit_vector(s);
+        return Self {
+            s,
+            t: cross(n, s),
+            n,
+        };
+    }
+
+    pub fn to_local(self, v: Vec3) -> Vec3 {
+        return Vec3::new(dot(v, self.s), dot(v, self.t), dot(v, self.n));
+    }
+
+    pub fn to_world(self, v: Vec3) -> Vec3 {
+        return v.x * self.s + v.y * self.t + v.z * self.n;
+    }
+}
+
+// Trowbridge-Reitz (GGX) distribution of microfacet normals in the local
+// frame, with height correlated Smith masking-shadowing. Anisotropic ones are
+// rougher along one of x and y than the other.
+#[derive(Copy, Clone)]
+pub struct Ggx {
+    alpha_x: f32,
+    alpha_y: f32,
+}
+
+// Roughness is squared into alpha so it looks perceptually linear. Very small
+// alphas are held at a minimum, where the lobe is sharp enough to pass for a
+// mirror but the numbers stay finite.
+fn roughness_to_alpha(roughness: f32) -> f32 {
+    return f32::max(roughness * roughness, 1e-3);
+}
+
+impl Ggx {
+    pub fn new(roughness: f32) -> Self {
+        return Ggx::anisotropic(roughness, roughness);
+    }
+
+    pub fn anisotropic(roughness_x: f32, roughness_y: f32) -> Self {
+        Self {
+            alpha_x: roughness_to_alpha(roughness_x),
+            alpha_y: roughness_to_alpha(roughness_y),
+        }
+    }
+
+    pub fn d(&self, m: Vec3) -> f32 {
+        if m.z <= 0.0 {
+            return 0.0;
+        }
+        let x = m.x / self.alpha_x;
+        let y = m.y / self.alpha_y;
+        let denominator = x * x + y * y + m.z * m.z;
+        return 1.0 / (PI * self.alpha_x * self.alpha_y * denominator * denominator);
+    }
+
+    fn lambda(&self, w: Vec3) -> f32 {
+        let cos2 = w.z * w.z;
+        if cos2 <= 0.0 {
+            return INFINITY;
+        }
+        let x = self.alpha_x * w.x;
+        let y = self.alpha_y * w.y;
+        return 0.5 * (f32::sqrt(1.0 + (x * x + y * y) / cos2) - 1.0);
+    }
+
+    pub fn g1(&self, w: Vec3) -> f32 {
+        return 1.0 / (1.0 + self.lambda(w));
+    }
+
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> f32 {
+        return 1.0 / (1.0 + self.lambda(wo) + self.lambda(wi));
+    }
+
+    // Samples a normal visible from wo, proportionally to its projected area
+    // (Heitz 2018, "Sampling the GGX Distribution of Visible Normals").
+    pub fn sample_visible(&self, wo: Vec3, u: f32, v: f32) -> Vec3 {
+        // Stretch to the hemisphere configuration.
+        let vh = unit_vector(Vec3::new(self.alpha_x * wo.x, self.alpha_y * wo.y, wo.z));
+        let length_squared = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if length_squared > 0.0 {
+            Vec3::new(-vh.y, vh.x, 0.0) / f32::sqrt(length_squared)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = cross(vh, t1);
+
+        // Point on the projected disk, squeezed towards the visible half.
+        let r = f32::sqrt(u);
+        let phi = 2.0 * PI * v;
+        let p1 = r * f32::cos(phi);
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * f32::sqrt(f32::max(1.0 - p1 * p1, 0.0)) + s * r * f32::sin(phi);
+        let nh = p1 * t1 + p2 * t2 + f32::sqrt(f32::max(1.0 - p1 * p1 - p2 * p2, 0.0)) * vh;
+
+        // And back to the ellipsoid.
+        return unit_vector(Vec3::new(
+            self.alpha_x * nh.x,
+            self.alpha_y * nh.y,
+            f32::max(nh.z, 1e-6),
+        ));
+    }
+
+    // Density of sample_visible picking m.
+    pub fn visible_pdf(&self, wo: Vec3, m: Vec3) -> f32 {
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        return self.g1(wo) * f32::max(dot(wo, m), 0.0) * self.d(m) / wo.z;
+    }
+
+    // Mirrors wo about a visible normal, None if that ends up below the
+    // surface.
+    pub fn sample_reflection(&self, wo: Vec3, u: f32, v: f32) -> Option<Vec3> {
+        let wi = reflect(-wo, self.sample_visible(wo, u, v));
+        if wi.z <= 0.0 {
+            return None;
+        }
+        return Some(wi);
+    }
+
+    // Reflection lobe times the cosine, leaving out Fresnel.
+    pub fn eval_reflection(&self, wo: Vec3, wi: Vec3) -> f32 {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let m = unit_vector(wo + wi);
+        return self.d(m) * self.g(wo, wi) / (4.0 * wo.z);
+    }
+
+    pub fn pdf_reflection(&self, wo: Vec3, wi: Vec3) -> f32 {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let m = unit_vector(wo + wi);
+        return self.visible_pdf(wo, m) / (4.0 * dot(wo, m));
+    }
+
+    // Reflects or refracts wo through a visible normal, picking by Fresnel
+    // with w, where eta is the index ratio across the surface seen from wo.
+    pub fn sample_dielectric(&self, wo: Vec3, eta: f32, u: f32, v: f32, w: f32) -> Option<Vec3> {
+        let m = self.sample_visible(wo, u, v);
+        let cos_i = dot(wo, m);
+        if w < fresnel_dielectric(cos_i, eta) {
+            let wi = reflect(-wo, m);
+            if wi.z <= 0.0 {
+                return None;
+            }
+            return Some(wi);
+        }
+
+        let cos_t = f32::sqrt(f32::max(1.0 - (1.0 - cos_i * cos_i) / (eta * eta), 0.0));
+        let wi = -wo / eta + (cos_i / eta - cos_t) * m;
+        if wi.z >= 0.0 {
+            return None;
+        }
+        return Some(unit_vector(wi));
+    }
+
+    // Reflection and transmission lobes times the cosine (Walter et al. 2007,
+    // "Microfacet Models for Refraction through Rough Surfaces"). Radiance
+    // isn't scaled by the squared index ratio, which cancels out on paths that
+    // leave the surface again.
+    pub fn eval_dielectric(&self, wo: Vec3, wi: Vec3, eta: f32) -> f32 {
+        let m = match dielectric_half_vector(wo, wi, eta) {
+            Some(m) => m,
+            None => return 0.0,
+        };
+
+        let d = self.d(m);
+        let g = self.g(wo, wi);
+        let fresnel = fresnel_dielectric(dot(wo, m), eta);
+        if wi.z > 0.0 {
+            return fresnel * d * g / (4.0 * wo.z);
+        }
+        let denominator = dot(wo, m) + eta * dot(wi, m);
+        return eta * eta * (1.0 - fresnel) * d * g * dot(wo, m) * f32::abs(dot(wi, m))
+            / (wo.z * denominator * denominator);
+    }
+
+    pub fn pdf_dielectric(&self, wo: Vec3, wi: Vec3, eta: f32) -> f32 {
+        let m = match dielectric_half_vector(wo, wi, eta) {
+            Some(m) => m,
+            None => return 0.0,
+        };
+
+        let visible = self.visible_pdf(wo, m);
+        let fresnel = fresnel_dielectric(dot(wo, m), eta);
+        if wi.z > 0.0 {
+            return fresnel * visible / (4.0 * dot(wo, m));
+        }
+        let denominator = dot(wo, m) + eta * dot(wi, m);
+        return (1.0 - fresnel) * visible * eta * eta * f32::abs(dot(wi, m))
+            / (denominator * denominator);
+    }
+}
+
+// Microfacet normal that reflects or refracts wo into wi, facing wo's side,
+// or None if no microfacet does.
+fn dielectric_half_vector(wo: Vec3, wi: Vec3, eta: f32) -> Option<Vec3> {
+    if wo.z <= 0.0 || wi.z == 0.0 {
+        return None;
+    }
+
+    let reflection = wi.z > 0.0;
+    let h = if reflection { wo + wi } else { wo + eta * wi };
+    if h.near_zero() {
+        return None;
+    }
+
+    let mut m = unit_vector(h);
+    if m.z < 0.0 {
+        m = -m;
+    }
+    // Both directions have to be on the sides the microfacet sends them.
+    if dot(wo, m) <= 0.0 || (dot(wi, m) > 0.0) != reflection {
+        return None;
+    }
+    return Some(m);
+}
+
+// Unpolarized Fresnel reflectance of a dielectric boundary, where eta is the
+// index on the far side over the index on the near side. Total internal
+// reflection gives 1.
+pub fn fresnel_dielectric(cos_i: f32, eta: f32) -> f32 {
+    let cos_i = clamp(cos_i, 0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = f32::sqrt(1.0 - sin2_t);
+
+    let parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    return 0.5 * (parallel * parallel + perpendicular * perpendicular);
+}
+
+// Fresnel reflectance of a conductor with complex index eta + ik in air, per
+// color channel.
+pub fn fresnel_conductor(cos_i: f32, eta: Vec3, k: Vec3) -> Vec3 {
+    let channel = |eta: f32, k: f32| -> f32 {
+        let cos2 = clamp(cos_i * cos_i, 0.0, 1.0);
+        let sin2 = 1.0 - cos2;
+        let eta2 = eta * eta;
+        let k2 = k * k;
+
+        let t0 = eta2 - k2 - sin2;
+        let a2_plus_b2 = f32::sqrt(t0 * t0 + 4.0 * eta2 * k2);
+        let a = f32::sqrt(f32::max(0.5 * (a2_plus_b2 + t0), 0.0));
+
+        let t1 = a2_plus_b2 + cos2;
+        let t2 = 2.0 * cos_i * a;
+        let rs = (t1 - t2) / (t1 + t2);
+
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        return 0.5 * (rp + rs);
+    };
+    return Vec3::new(
+        channel(eta.x, k.x),
+        channel(eta.y, k.y),
+        channel(eta.z, k.z),
+    );
+}
+
+// Reflectance of a film of the given index and thickness in nanometers,
+// between air and a dielectric substrate, at one wavelength in nanometers.
+// Light bouncing inside the film interferes with itself, with a phase that
+// follows the thickness over the wavelength (Airy's formula, averaged over
+// both polarizations).
+pub fn fresnel_thin_film(
+    cos_i: f32,
+    wavelength: f32,
+    thickness: f32,
+    film_ir: f32,
+    substrate_ir: f32,
+) -> f32 {
+    let cos_i = clamp(cos_i, 0.0, 1.0);
+    let sin2_i = 1.0 - cos_i * cos_i;
+    let cos_film = f32::sqrt(f32::max(1.0 - sin2_i / (film_ir * film_ir), 0.0));
+    let cos_substrate = f32::sqrt(f32::max(1.0 - sin2_i / (substrate_ir * substrate_ir), 0.0));
+
+    let phase = 4.0 * PI * film_ir * thickness * cos_film / wavelength;
+    let airy = |r12: f32, r23: f32| -> f32 {
+        let cross = 2.0 * r12 * r23 * f32::cos(phase);
+        return (r12 * r12 + r23 * r23 + cross) / (1.0 + r12 * r12 * r23 * r23 + cross);
+    };
+
+    let perpendicular = airy(
+        (cos_i - film_ir * cos_film) / (cos_i + film_ir * cos_film),
+        (film_ir * cos_film - substrate_ir * cos_substrate)
+            / (film_ir * cos_film + substrate_ir * cos_substrate),
+    );
+    let parallel = airy(
+        (film_ir * cos_i - cos_film) / (film_ir * cos_i + cos_film),
+        (substrate_ir * cos_film - film_ir * cos_substrate)
+            / (substrate_ir * cos_film + film_ir * cos_substrate),
+    );
+    return clamp(0.5 * (perpendicular + parallel), 0.0, 1.0);
+}
+
+// Schlick's approximation of Fresnel reflectance, from the reflectance at
+// normal incidence.
+pub fn fresnel_schlick(cos_i: f32, f0: Vec3) -> Vec3 {
+    let weight = f32::powf(1.0 - clamp(cos_i, 0.0, 1.0), 5.0);
+    return f0 + (Vec3::new(1.0, 1.0, 1.0) - f0) * weight;
+}
+
+// Complex indices of refraction of some metals at 650, 550 and 450 nm.
+pub fn conductor_preset(name: &str) -> Option<(Vec3, Vec3)> {
+    return match name {
+        "gold" => Some((
+            Vec3::new(0.143, 0.374, 1.442),
+            Vec3::new(3.983, 2.385, 1.603),
+        )),
+        "copper" => Some((
+            Vec3::new(0.200, 0.924, 1.102),
+            Vec3::new(3.912, 2.452, 2.142),
+        )),
+        "aluminium" => Some((
+            Vec3::new(1.657, 0.880, 0.521),
+            Vec3::new(9.224, 6.270, 4.837),
+        )),
+        _ => None,
+    };
+}
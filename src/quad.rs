@@ -0,0 +1,95 @@
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::*;
+use crate::math::vec3::*;
+use crate::ray::Ray;
+
+use std::sync::Arc;
+
+// Parallelogram with a corner at q and edges u and v. The front face is on
+// the side of cross(u, v).
+#[derive(Clone)]
+pub struct Quad {
+    pub q: Vec3,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub mat: Option<Arc<dyn Material>>,
+    normal: Vec3,
+    d: f32,
+    w: Vec3,
+}
+
+impl Quad {
+    pub fn new(q: Vec3, u: Vec3, v: Vec3, mat: Arc<dyn Material>) -> Self {
+        let n = cross(u, v);
+        let normal = unit_vector(n);
+        Self {
+            q,
+            u,
+            v,
+            mat: Some(mat),
+            normal,
+            d: dot(normal, q),
+            w: n / dot(n, n),
+        }
+    }
+
+    pub fn normal(&self) -> Vec3 {
+        return self.normal;
+    }
+
+    pub fn area(&self) -> f32 {
+        return cross(self.u, self.v).length();
+    }
+
+    // Distance along the ray to the quad, if it crosses it, and where along u
+    // and v.
+    pub fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(f32, f32, f32)> {
+        let denominator = dot(self.normal, ray.direction);
+        if f32::abs(denominator) < 1e-8 {
+            return None;
+        }
+
+        let t = (self.d - dot(self.normal, ray.origin)) / denominator;
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        let planar = ray.at(t) - self.q;
+        let alpha = dot(self.w, cross(planar, self.v));
+        let beta = dot(self.w, cross(self.u, planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+
+        return Some((t, alpha, beta));
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32, record: &mut HitRecord) -> bool {
+        let (t, u, v) = match self.intersect(ray, t_min, t_max) {
+            Some(hit) => hit,
+            None => return false,
+        };
+
+        record.t = t;
+        record.u = u;
+        record.v = v;
+        record.dpdu = self.u;
+        record.dpdv = self.v;
+        record.point = ray.at(t);
+        record.mat = self.mat.clone();
+        record.set_face_normal(ray, &self.normal);
+
+        return record.alpha_test();
+    }
+
+    fn describe(&self) -> String {
+        let mut description = format!("Quad corner {} u {} v {}", self.q, self.u, self.v);
+        if let Some(mat) = &self.mat {
+            description.push_str(" with ");
+            description.push_str(&mat.describe());
+        }
+        return description;
+    }
+}
@@ -4,11 +4,27 @@ use crate::math::vec3::Vec3;
 pub struct Ray {
     pub origin: Vec3,
     pub direction: Vec3,
+    pub time: f32,
+    // Wavelength in nanometers carried in spectral mode, 0 otherwise.
+    pub wavelength: f32,
 }
 
 impl Ray {
     pub fn new(origin: Vec3, direction: Vec3) -> Ray {
-        Ray { origin, direction }
+        Ray {
+            origin,
+            direction,
+            time: 0.0,
+            wavelength: 0.0,
+        }
+    }
+    pub fn with_time(origin: Vec3, direction: Vec3, time: f32) -> Ray {
+        Ray {
+            origin,
+            direction,
+            time,
+            wavelength: 0.0,
+        }
     }
     pub fn at(&self, t: f32) -> Vec3 {
         return self.origin + t * self.direction;
@@ -0,0 +1,289 @@
+use crate::consts::random;
+
+// Supplies the random numbers for one camera sample. Dimensions are consumed in
+// a fixed order: pixel (2D), lens (2D), time (1D), then whatever each bounce asks
+// for, so every sampler sees the same layout.
+pub trait Sampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: u32);
+    fn get_1d(&mut self) -> f32;
+    fn get_2d(&mut self) -> (f32, f32);
+}
+
+pub fn from_name(name: &str, samples_per_pixel: u32, seed: u64) -> Option<Box<dyn Sampler>> {
+    return match name {
+        "independent" => Some(Box::new(IndependentSampler::new())),
+        "stratified" => Some(Box::new(StratifiedSampler::new(samples_per_pixel, seed))),
+        "halton" => Some(Box::new(HaltonSampler::new(seed))),
+        "sobol" => Some(Box::new(SobolSampler::new(seed))),
+        _ => None,
+    };
+}
+
+pub struct IndependentSampler {}
+
+impl IndependentSampler {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, _x: usize, _y: usize, _index: u32) {}
+
+    fn get_1d(&mut self) -> f32 {
+        return random();
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        return (random(), random());
+    }
+}
+
+// Jittered strata for every dimension, shuffled independently per pixel and
+// dimension so the strata of different dimensions don't line up.
+pub struct StratifiedSampler {
+    samples_per_pixel: u32,
+    seed: u64,
+    pixel_hash: u64,
+    index: u32,
+    dimension: u32,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: u32, seed: u64) -> Self {
+        Self {
+            samples_per_pixel: samples_per_pixel.max(1),
+            seed,
+            pixel_hash: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn stratum(&mut self, count: u32) -> u32 {
+        let h = mix(self.pixel_hash, self.dimension as u64);
+        self.dimension += 1;
+
+        // Past samples_per_pixel the strata are reused with a fresh shuffle.
+        let round = self.index / self.samples_per_pixel;
+        let i = self.index % self.samples_per_pixel;
+        return permutation_element(i % count, count, mix(h, round as u64) as u32);
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: u32) {
+        self.pixel_hash = mix(mix(self.seed, x as u64), y as u64);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let n = self.samples_per_pixel;
+        let s = self.stratum(n);
+        return (s as f32 + random()) / n as f32;
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        let nx = f32::sqrt(self.samples_per_pixel as f32) as u32;
+        let ny = self.samples_per_pixel.div_ceil(nx);
+        let s = self.stratum(nx * ny);
+        return (
+            ((s % nx) as f32 + random()) / nx as f32,
+            ((s / nx) as f32 + random()) / ny as f32,
+        );
+    }
+}
+
+// Halton sequence with one prime base per dimension and a per pixel
+// Cranley-Patterson rotation to decorrelate neighbouring pixels.
+pub struct HaltonSampler {
+    primes: Vec<u32>,
+    seed: u64,
+    pixel_hash: u64,
+    index: u32,
+    dimension: u32,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            primes: primes_below(1000),
+            seed,
+            pixel_hash: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: u32) {
+        self.pixel_hash = mix(mix(self.seed, x as u64), y as u64);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let dimension = self.dimension as usize;
+        self.dimension += 1;
+
+        // Very long paths run out of bases and fall back to plain random numbers.
+        if dimension >= self.primes.len() {
+            return random();
+        }
+
+        let offset = to_unit_float(mix(self.pixel_hash, dimension as u64) as u32);
+        let v = radical_inverse(self.primes[dimension], self.index) + offset;
+        return if v >= 1.0 { v - 1.0 } else { v };
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        return (self.get_1d(), self.get_1d());
+    }
+}
+
+// The first two Sobol dimensions reused for every dimension pair, with the
+// sample order shuffled and the points Owen scrambled per pixel and dimension
+// (Burley 2020, "Practical Hash-based Owen Scrambling").
+pub struct SobolSampler {
+    seed: u64,
+    pixel_hash: u64,
+    index: u32,
+    dimension: u32,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel_hash: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: u32) {
+        self.pixel_hash = mix(mix(self.seed, x as u64), y as u64);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        return self.get_2d().0;
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        let h = mix(self.pixel_hash, self.dimension as u64);
+        self.dimension += 2;
+
+        let index = nested_uniform_scramble(self.index, mix(h, 0) as u32);
+        let x = nested_uniform_scramble(sobol_0(index), mix(h, 1) as u32);
+        let y = nested_uniform_scramble(sobol_1(index), mix(h, 2) as u32);
+        return (to_unit_float(x), to_unit_float(y));
+    }
+}
+
+fn sobol_0(index: u32) -> u32 {
+    return index.reverse_bits();
+}
+
+fn sobol_1(mut index: u32) -> u32 {
+    let mut v: u32 = 1 << 31;
+    let mut result = 0;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= v;
+        }
+        index >>= 1;
+        v ^= v >> 1;
+    }
+    return result;
+}
+
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    return x;
+}
+
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    return laine_karras_permutation(x.reverse_bits(), seed).reverse_bits();
+}
+
+fn radical_inverse(base: u32, mut index: u32) -> f32 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_n = 1.0;
+    let mut reversed: f64 = 0.0;
+    while index > 0 {
+        let next = index / base;
+        let digit = index - next * base;
+        reversed = reversed * base as f64 + digit as f64;
+        inv_base_n *= inv_base;
+        index = next;
+    }
+    return f64::min(reversed * inv_base_n, ONE_MINUS_EPSILON as f64) as f32;
+}
+
+fn primes_below(n: u32) -> Vec<u32> {
+    let mut primes = Vec::new();
+    for candidate in 2..n {
+        if primes.iter().all(|p| candidate % p != 0) {
+            primes.push(candidate);
+        }
+    }
+    return primes;
+}
+
+// Element i of a random permutation of 0..l chosen by p (Kensler 2013,
+// "Correlated Multi-Jittered Sampling").
+fn permutation_element(mut i: u32, l: u32, p: u32) -> u32 {
+    let mut w = l - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            break;
+        }
+    }
+    return ((i as u64 + p as u64) % l as u64) as u32;
+}
+
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+fn to_unit_float(bits: u32) -> f32 {
+    return f32::min(bits as f32 * (1.0 / 4294967296.0), ONE_MINUS_EPSILON);
+}
+
+fn mix(h: u64, value: u64) -> u64 {
+    let mut z = h ^ value.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    return z ^ (z >> 31);
+}
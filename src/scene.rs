@@ -0,0 +1,48 @@
+use crate::environment::Environment;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::light::Light;
+
+use std::sync::Arc;
+
+// Everything a ray can meet: the objects and the environment around them,
+// plus the lights that are only reached by sampling them.
+pub struct Scene {
+    pub world: HittableList,
+    pub environment: Arc<dyn Environment>,
+    pub lights: Vec<Arc<dyn Light>>,
+}
+
+impl Scene {
+    // Area lights add their shapes to the world.
+    pub fn new(
+        mut world: HittableList,
+        environment: Arc<dyn Environment>,
+        lights: Vec<Arc<dyn Light>>,
+    ) -> Self {
+        for light in &lights {
+            if let Some(shape) = light.shape() {
+                world.add(shape);
+            }
+        }
+
+        Self {
+            world,
+            environment,
+            lights,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let mut description = format!(
+            "{}\nenvironment {}",
+            self.world.describe(),
+            self.environment.describe()
+        );
+        for light in &self.lights {
+            description.push_str("\nlight ");
+            description.push_str(&light.describe());
+        }
+        return description;
+    }
+}
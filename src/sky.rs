@@ -0,0 +1,257 @@
+use crate::consts::*;
+use crate::environment::Environment;
+use crate::math::vec3::*;
+
+// Sky luminance in kcd/m^2 to scene radiance, bright enough to light the
+// scene without tone mapping.
+const SKY_SCALE: f32 = 0.025;
+// Luminance of the sun outside the atmosphere, in kcd/m^2.
+const SUN_LUMINANCE: f32 = 1.6e6;
+// Angular diameter of the real sun in degrees.
+const SUN_DIAMETER: f32 = 0.53;
+// Share of explicit light samples aimed at the sun while it is up.
+const SUN_SAMPLE_PROBABILITY: f32 = 0.5;
+
+// Analytic daylight (Preetham, Shirley and Smits 1999, "A Practical Analytic
+// Model for Daylight") with a sun disk and a flat ground below the horizon.
+pub struct PhysicalSky {
+    sun_direction: Vec3,
+    turbidity: f32,
+    ground_albedo: Vec3,
+    // Angular diameter in degrees, the sun keeps its power when it grows so
+    // only the shadows get softer.
+    sun_size: f32,
+    intensity: f32,
+    // Perez coefficients for Y, x and y, and their zenith values.
+    perez: [[f32; 5]; 3],
+    zenith: [f32; 3],
+    cos_sun_radius: f32,
+    sun_radiance: Vec3,
+    ground_radiance: Vec3,
+}
+
+impl PhysicalSky {
+    // Elevation is measured up from the horizon and azimuth clockwise from -z
+    // towards +x, both in degrees.
+    pub fn new(
+        elevation: f32,
+        azimuth: f32,
+        turbidity: f32,
+        ground_albedo: Vec3,
+        sun_size: f32,
+        intensity: f32,
+    ) -> Self {
+        let elevation_rad = degrees_to_radians(elevation);
+        let azimuth_rad = degrees_to_radians(azimuth);
+        let sun_direction = Vec3::new(
+            f32::cos(elevation_rad) * f32::sin(azimuth_rad),
+            f32::sin(elevation_rad),
+            -f32::cos(elevation_rad) * f32::cos(azimuth_rad),
+        );
+
+        let t = turbidity;
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        // The zenith fits only hold with the sun above the horizon.
+        let theta_s = f32::min(PI / 2.0 - elevation_rad, PI / 2.0);
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_y = f32::max(
+            (4.0453 * t - 4.9710) * f32::tan(chi) - 0.2155 * t + 2.4192,
+            0.0,
+        );
+        let (s1, s2, s3) = (theta_s, theta_s * theta_s, theta_s * theta_s * theta_s);
+        let zenith_x = t * t * (0.00166 * s3 - 0.00375 * s2 + 0.00209 * s1)
+            + t * (-0.02903 * s3 + 0.06377 * s2 - 0.03202 * s1 + 0.00394)
+            + (0.11693 * s3 - 0.21196 * s2 + 0.06052 * s1 + 0.25886);
+        let zenith_yc = t * t * (0.00275 * s3 - 0.00610 * s2 + 0.00317 * s1)
+            + t * (-0.04214 * s3 + 0.08970 * s2 - 0.04153 * s1 + 0.00516)
+            + (0.15346 * s3 - 0.26756 * s2 + 0.06670 * s1 + 0.26688);
+
+        let mut sky = Self {
+            sun_direction,
+            turbidity,
+            ground_albedo,
+            sun_size,
+            intensity,
+            perez,
+            zenith: [zenith_y, zenith_x, zenith_yc],
+            cos_sun_radius: f32::cos(degrees_to_radians(sun_size / 2.0)),
+            sun_radiance: Vec3::new(0.0, 0.0, 0.0),
+            ground_radiance: Vec3::new(0.0, 0.0, 0.0),
+        };
+
+        if elevation > -sun_size / 2.0 {
+            let size_scale = (SUN_DIAMETER / sun_size) * (SUN_DIAMETER / sun_size);
+            sky.sun_radiance =
+                SKY_SCALE * SUN_LUMINANCE * size_scale * sun_transmittance(theta_s, turbidity);
+        }
+        sky.ground_radiance = sky.ground_albedo * sky.horizontal_irradiance() / PI;
+
+        return sky;
+    }
+
+    // Relative luminance or chromaticity of the sky from the Perez formula.
+    fn perez(&self, coefficients: &[f32; 5], cos_theta: f32, gamma: f32) -> f32 {
+        let [a, b, c, d, e] = *coefficients;
+        let cos_gamma = f32::cos(gamma);
+        return (1.0 + a * f32::exp(b / cos_theta))
+            * (1.0 + c * f32::exp(d * gamma) + e * cos_gamma * cos_gamma);
+    }
+
+    fn sky_radiance(&self, direction: Vec3) -> Vec3 {
+        // Close to the horizon the formula blows up, so it is held there.
+        let cos_theta = f32::max(direction.y, 0.01);
+        let gamma = f32::acos(clamp(dot(direction, self.sun_direction), -1.0, 1.0));
+        let theta_s = f32::acos(clamp(self.sun_direction.y, 0.0, 1.0));
+
+        let [luminance, x, y]: [f32; 3] = std::array::from_fn(|i| {
+            self.zenith[i] * self.perez(&self.perez[i], cos_theta, gamma)
+                / self.perez(&self.perez[i], 1.0, theta_s)
+        });
+        if luminance <= 0.0 || y <= 0.0 {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+
+        // xyY to XYZ to linear sRGB.
+        let cx = x / y * luminance;
+        let cz = (1.0 - x - y) / y * luminance;
+        let r = 3.2406 * cx - 1.5372 * luminance - 0.4986 * cz;
+        let g = -0.9689 * cx + 1.8758 * luminance + 0.0415 * cz;
+        let b = 0.0557 * cx - 0.2040 * luminance + 1.0570 * cz;
+        return SKY_SCALE * Vec3::new(f32::max(r, 0.0), f32::max(g, 0.0), f32::max(b, 0.0));
+    }
+
+    // Light falling on the ground from the sky and the sun, for its color.
+    fn horizontal_irradiance(&self) -> Vec3 {
+        let (steps_theta, steps_phi) = (32, 64);
+        let d_theta = PI / 2.0 / steps_theta as f32;
+        let d_phi = 2.0 * PI / steps_phi as f32;
+
+        let mut irradiance = Vec3::new(0.0, 0.0, 0.0);
+        for i in 0..steps_theta {
+            let theta = (i as f32 + 0.5) * d_theta;
+            for j in 0..steps_phi {
+                let phi = (j as f32 + 0.5) * d_phi;
+                let direction = Vec3::new(
+                    f32::sin(theta) * f32::cos(phi),
+                    f32::cos(theta),
+                    f32::sin(theta) * f32::sin(phi),
+                );
+                let solid_angle = f32::sin(theta) * d_theta * d_phi;
+                irradiance =
+                    irradiance + self.sky_radiance(direction) * f32::cos(theta) * solid_angle;
+            }
+        }
+
+        let sun_solid_angle = 2.0 * PI * (1.0 - self.cos_sun_radius);
+        let sun_cosine = f32::max(self.sun_direction.y, 0.0);
+        return irradiance + self.sun_radiance * sun_solid_angle * sun_cosine;
+    }
+
+    fn sun_visible(&self) -> bool {
+        return !self.sun_radiance.near_zero();
+    }
+}
+
+// Fraction of sunlight passing through the atmosphere at the red, green and
+// blue wavelengths, from Rayleigh and aerosol (Angstrom) extinction along the
+// relative optical mass (Preetham et al., appendix A.2).
+fn sun_transmittance(theta_s: f32, turbidity: f32) -> Vec3 {
+    let theta_deg = theta_s * 180.0 / PI;
+    let mass = 1.0 / (f32::cos(theta_s) + 0.15 * f32::powf(93.885 - theta_deg, -1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+
+    let mut channels = [0.0; 3];
+    // Wavelengths in micrometers.
+    for (i, lambda) in [0.680, 0.550, 0.440].iter().enumerate() {
+        let rayleigh = f32::exp(-0.008735 * f32::powf(*lambda, -4.08) * mass);
+        let aerosol = f32::exp(-beta * f32::powf(*lambda, -1.3) * mass);
+        channels[i] = rayleigh * aerosol;
+    }
+    return Vec3::new(channels[0], channels[1], channels[2]);
+}
+
+impl Environment for PhysicalSky {
+    fn eval(&self, direction: Vec3) -> Vec3 {
+        let d = unit_vector(direction);
+        if d.y < 0.0 {
+            return self.intensity * self.ground_radiance;
+        }
+
+        let mut radiance = self.sky_radiance(d);
+        if self.sun_visible() && dot(d, self.sun_direction) >= self.cos_sun_radius {
+            radiance = radiance + self.sun_radiance;
+        }
+        return self.intensity * radiance;
+    }
+
+    // Half the samples go into the sun disk, the rest uniformly over the sphere.
+    fn sample(&self, u: f32, v: f32) -> Option<(Vec3, Vec3, f32)> {
+        let direction = if self.sun_visible() && u < SUN_SAMPLE_PROBABILITY {
+            let u = u / SUN_SAMPLE_PROBABILITY;
+            let cos_theta = 1.0 - u * (1.0 - self.cos_sun_radius);
+            let sin_theta = f32::sqrt(f32::max(1.0 - cos_theta * cos_theta, 0.0));
+            let phi = 2.0 * PI * v;
+
+            let w = self.sun_direction;
+            let a = if f32::abs(w.x) > 0.9 {
+                Vec3::new(0.0, 1.0, 0.0)
+            } else {
+                Vec3::new(1.0, 0.0, 0.0)
+            };
+            let s = unit_vector(cross(w, a));
+            let t = cross(w, s);
+            sin_theta * f32::cos(phi) * s + sin_theta * f32::sin(phi) * t + cos_theta * w
+        } else {
+            let u = if self.sun_visible() {
+                (u - SUN_SAMPLE_PROBABILITY) / (1.0 - SUN_SAMPLE_PROBABILITY)
+            } else {
+                u
+            };
+            Vec3::uniform_sphere(u, v)
+        };
+
+        return Some((direction, self.eval(direction), self.pdf(direction)));
+    }
+
+    fn pdf(&self, direction: Vec3) -> f32 {
+        if !self.sun_visible() {
+            return 1.0 / (4.0 * PI);
+        }
+
+        let mut pdf = (1.0 - SUN_SAMPLE_PROBABILITY) / (4.0 * PI);
+        if dot(unit_vector(direction), self.sun_direction) >= self.cos_sun_radius {
+            pdf += SUN_SAMPLE_PROBABILITY / (2.0 * PI * (1.0 - self.cos_sun_radius));
+        }
+        return pdf;
+    }
+
+    fn describe(&self) -> String {
+        return format!(
+            "sky sun {} turbidity {} ground {} sun size {} intensity {}",
+            self.sun_direction, self.turbidity, self.ground_albedo, self.sun_size, self.intensity
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use crate::math::vec3::Vec3;
+
+use std::sync::OnceLock;
+
+// Visible range sampled in spectral mode, in nanometers.
+pub const WAVELENGTH_MIN: f32 = 380.0;
+pub const WAVELENGTH_MAX: f32 = 720.0;
+// Where refractive indices are quoted, the sodium d line, used by rays that
+// carry no wavelength.
+pub const WAVELENGTH_D_LINE: f32 = 587.6;
+
+// Smits 1999, "An RGB-to-Spectrum Conversion for Reflectances": spectra of
+// the primaries and their complements in ten even bins over the visible range.
+const SMITS_WHITE: [f32; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const SMITS_CYAN: [f32; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const SMITS_MAGENTA: [f32; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const SMITS_YELLOW: [f32; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f32; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f32; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const SMITS_BLUE: [f32; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+// Picks a wavelength uniformly over the visible range, with its pdf.
+pub fn sample_wavelength(u: f32) -> (f32, f32) {
+    let wavelength = WAVELENGTH_MIN + u * (WAVELENGTH_MAX - WAVELENGTH_MIN);
+    return (wavelength, 1.0 / (WAVELENGTH_MAX - WAVELENGTH_MIN));
+}
+
+// Value at the given wavelength of a smooth spectrum with this linear RGB
+// color, built from white, one secondary and one primary.
+pub fn rgb_to_spectrum(color: Vec3, wavelength: f32) -> f32 {
+    let t = (wavelength - WAVELENGTH_MIN) / (WAVELENGTH_MAX - WAVELENGTH_MIN);
+    let bin = ((t * 10.0) as i32).clamp(0, 9) as usize;
+    let (r, g, b) = (color.x, color.y, color.z);
+
+    let mut value;
+    if r <= g && r <= b {
+        value = r * SMITS_WHITE[bin];
+        if g <= b {
+            value += (g - r) * SMITS_CYAN[bin] + (b - g) * SMITS_BLUE[bin];
+        } else {
+            value += (b - r) * SMITS_CYAN[bin] + (g - b) * SMITS_GREEN[bin];
+        }
+    } else if g <= r && g <= b {
+        value = g * SMITS_WHITE[bin];
+        if r <= b {
+            value += (r - g) * SMITS_MAGENTA[bin] + (b - r) * SMITS_BLUE[bin];
+        } else {
+            value += (b - g) * SMITS_MAGENTA[bin] + (r - b) * SMITS_RED[bin];
+        }
+    } else {
+        value = b * SMITS_WHITE[bin];
+        if r <= g {
+            value += (r - b) * SMITS_YELLOW[bin] + (g - r) * SMITS_GREEN[bin];
+        } else {
+            value += (g - b) * SMITS_YELLOW[bin] + (r - g) * SMITS_RED[bin];
+        }
+    }
+    return f32::max(value, 0.0);
+}
+
+// Piecewise Gaussian with different widths on either side of its peak.
+fn lobe(wavelength: f32, peak: f32, left: f32, right: f32) -> f32 {
+    let width = if wavelength < peak { left } else { right };
+    let t = (wavelength - peak) / width;
+    return f32::exp(-0.5 * t * t);
+}
+
+// CIE 1931 color matching functions, from the multi-lobe fit of Wyman, Sloan
+// and Shirley 2013, "Simple Analytic Approximations to the CIE XYZ Color
+// Matching Functions".
+fn color_matching(wavelength: f32) -> Vec3 {
+    let l = wavelength;
+    return Vec3::new(
+        1.056 * lobe(l, 599.8, 37.9, 31.0) + 0.362 * lobe(l, 442.0, 16.0, 26.7)
+            - 0.065 * lobe(l, 501.1, 20.4, 26.2),
+        0.821 * lobe(l, 568.8, 46.9, 40.5) + 0.286 * lobe(l, 530.9, 16.3, 31.1),
+        1.217 * lobe(l, 437.0, 11.8, 36.0) + 0.681 * lobe(l, 459.0, 26.0, 13.8),
+    );
+}
+
+fn xyz_to_linear_srgb(xyz: Vec3) -> Vec3 {
+    return Vec3::new(
+        3.2406 * xyz.x - 1.5372 * xyz.y - 0.4986 * xyz.z,
+        -0.9689 * xyz.x + 1.8758 * xyz.y + 0.0415 * xyz.z,
+        0.0557 * xyz.x - 0.2040 * xyz.y + 1.0570 * xyz.z,
+    );
+}
+
+// Linear sRGB of a flat spectrum of 1, integrated once. Dividing by it keeps
+// white surfaces under white lights white, rather than the pink of the equal
+// energy illuminant.
+fn flat_spectrum_rgb() -> Vec3 {
+    static FLAT: OnceLock<Vec3> = OnceLock::new();
+    return *FLAT.get_or_init(|| {
+        let steps = 1000;
+        let step = (WAVELENGTH_MAX - WAVELENGTH_MIN) / steps as f32;
+        let mut xyz = Vec3::new(0.0, 0.0, 0.0);
+        for i in 0..steps {
+            xyz = xyz + color_matching(WAVELENGTH_MIN + (i as f32 + 0.5) * step) * step;
+        }
+        return xyz_to_linear_srgb(xyz);
+    });
+}
+
+// Linear sRGB estimate from radiance carried at one wavelength picked with
+// the given pdf.
+pub fn wavelength_to_rgb(radiance: f32, wavelength: f32, pdf: f32) -> Vec3 {
+    let rgb = xyz_to_linear_srgb(color_matching(wavelength) * (radiance / pdf));
+    return rgb / flat_spectrum_rgb();
+}
+
+// A color as seen by a ray of the given wavelength: its spectrum's value in
+// every channel, or the color itself for rays without a wavelength.
+pub fn at_wavelength(color: Vec3, wavelength: f32) -> Vec3 {
+    if wavelength <= 0.0 {
+        return color;
+    }
+    let value = rgb_to_spectrum(color, wavelength);
+    return Vec3::new(value, value, value);
+}
+
+// Index of refraction as a function of wavelength.
+#[derive(Copy, Clone, Debug)]
+pub enum Ior {
+    Constant(f32),
+    // n = a + b / lambda^2, lambda in micrometers.
+    Cauchy { a: f32, b: f32 },
+    // n^2 = 1 + sum of b lambda^2 / (lambda^2 - c), lambda in micrometers.
+    Sellmeier { b: [f32; 3], c: [f32; 3] },
+}
+
+impl Ior {
+    // Glasses by name, with Sellmeier coefficients from their datasheets.
+    pub fn preset(name: &str) -> Option<Ior> {
+        return match name {
+            "bk7" => Some(Ior::Sellmeier {
+                b: [1.039_612, 0.231_792_34, 1.010_469_5],
+                c: [0.006_000_699, 0.020_017_914, 103.560_65],
+            }),
+            "flint" => Some(Ior::Sellmeier {
+                b: [1.737_597, 0.313_747_35, 1.898_781],
+                c: [0.013_188_707, 0.062_306_814, 155.236_3],
+            }),
+            "diamond" => Some(Ior::Sellmeier {
+                b: [0.3306, 4.3356, 0.0],
+                c: [0.030_625, 0.011_236, 0.0],
+            }),
+            _ => None,
+        };
+    }
+
+    // Rays without a wavelength see the index at the d line.
+    pub fn at(&self, wavelength: f32) -> f32 {
+        let wavelength = if wavelength > 0.0 {
+            wavelength
+        } else {
+            WAVELENGTH_D_LINE
+        };
+        let l2 = (wavelength / 1000.0) * (wavelength / 1000.0);
+        return match *self {
+            Ior::Constant(n) => n,
+            Ior::Cauchy { a, b } => a + b / l2,
+            Ior::Sellmeier { b, c } => {
+                let mut n2 = 1.0;
+                for i in 0..3 {
+                    n2 += b[i] * l2 / (l2 - c[i]);
+                }
+                f32::sqrt(f32::max(n2, 1.0))
+            }
+        };
+    }
+}
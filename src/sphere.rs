@@ -1,3 +1,4 @@
+use crate::consts::*;
 use crate::hittable::{HitRecord, Hittable};
 use crate::material::*;
 use crate::math::vec3::{dot, Vec3};
@@ -20,6 +21,34 @@ impl Sphere {
             mat: Some(mat),
         }
     }
+
+    // Fills in the hit at distance t along the ray.
+    fn fill_record(&self, ray: &Ray, t: f32, record: &mut HitRecord) {
+        record.t = t;
+        record.point = ray.at(t);
+
+        if let Some(mat) = &self.mat {
+            record.mat = Some(mat.clone());
+        }
+
+        record.normal = (record.point - self.center) / self.radius;
+
+        let outward_normal = (record.point - self.center) / self.radius;
+        record.set_face_normal(ray, &outward_normal);
+
+        // u goes around from -x through +z, v from the bottom to the top.
+        let theta = f32::acos(clamp(-outward_normal.y, -1.0, 1.0));
+        let phi = f32::atan2(-outward_normal.z, outward_normal.x) + PI;
+        record.u = phi / (2.0 * PI);
+        record.v = theta / PI;
+
+        // Derivatives of the point along those, which meet at the poles.
+        let (x, y, z) = (outward_normal.x, outward_normal.y, outward_normal.z);
+        let sin_theta = f32::max(f32::sqrt(x * x + z * z), 1e-6);
+        record.dpdu = 2.0 * PI * self.radius * Vec3::new(z, 0.0, -x);
+        record.dpdv =
+            PI * self.radius * Vec3::new(-x * y / sin_theta, sin_theta, -y * z / sin_theta);
+    }
 }
 
 impl Hittable for Sphere {
@@ -36,26 +65,25 @@ impl Hittable for Sphere {
 
         let sqrtd = f32::sqrt(discriminant);
 
-        let mut root = (-half_b - sqrtd) / a;
-        if root < t_min || t_max < root {
-            root = (-half_b + sqrtd) / a;
+        // The far side shows through where the near one is cut out.
+        for root in [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a] {
             if root < t_min || t_max < root {
-                return false;
+                continue;
+            }
+            self.fill_record(ray, root, record);
+            if record.alpha_test() {
+                return true;
             }
         }
+        return false;
+    }
 
-        record.t = root;
-        record.point = ray.at(record.t);
-
+    fn describe(&self) -> String {
+        let mut description = format!("Sphere center {} radius {}", self.center, self.radius);
         if let Some(mat) = &self.mat {
-            record.mat = Some(mat.clone());
+            description.push_str(" with ");
+            description.push_str(&mat.describe());
         }
-
-        record.normal = (record.point - self.center) / self.radius;
-
-        let outward_normal = (record.point - self.center) / self.radius;
-        record.set_face_normal(&ray, &outward_normal);
-
-        return true;
+        return description;
     }
 }
@@ -0,0 +1,140 @@
+use crate::hdr;
+use crate::hdr::HdrImage;
+use crate::math::vec3::*;
+
+use raylib::prelude::Image;
+
+// Color or parameter varying over a surface, looked up by the hit's surface
+// coordinates or its position.
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f32, v: f32, point: Vec3) -> Vec3;
+    fn describe(&self) -> String;
+}
+
+pub struct SolidColor {
+    color: Vec3,
+}
+
+impl SolidColor {
+    pub fn new(color: Vec3) -> Self {
+        Self { color }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f32, _v: f32, _point: Vec3) -> Vec3 {
+        return self.color;
+    }
+
+    fn describe(&self) -> String {
+        return format!("{}", self.color);
+    }
+}
+
+// Image mapped over the surface coordinates, with v = 0 at the bottom row,
+// bilinearly filtered and repeating outside [0, 1].
+pub struct ImageTexture {
+    source: String,
+    image: HdrImage,
+}
+
+impl ImageTexture {
+    pub fn new(source: &str, image: HdrImage) -> Self {
+        Self {
+            source: String::from(source),
+            image,
+        }
+    }
+
+    // HDR and EXR files are read as they are. Other images are 8 bit, and
+    // decoded from sRGB when they hold colors rather than parameters.
+    pub fn load(path: &str, srgb: bool) -> Result<ImageTexture, String> {
+        let lower = path.to_lowercase();
+        if lower.ends_with(".hdr") || lower.ends_with(".pic") || lower.ends_with(".exr") {
+            return Ok(ImageTexture::new(path, hdr::load(path)?));
+        }
+
+        let image = Image::load_image(path)?;
+        let decode = |c: u8| -> f32 {
+            let c = c as f32 / 255.0;
+            if !srgb {
+                return c;
+            }
+            if c <= 0.04045 {
+                return c / 12.92;
+            }
+            return f32::powf((c + 0.055) / 1.055, 2.4);
+        };
+        let pixels = image
+            .get_image_data()
+            .iter()
+            .map(|c| Vec3::new(decode(c.r), decode(c.g), decode(c.b)))
+            .collect();
+
+        return Ok(ImageTexture::new(
+            path,
+            HdrImage {
+                width: image.width() as usize,
+                height: image.height() as usize,
+                pixels,
+            },
+        ));
+    }
+
+    // Opacity mask from the image's alpha channel if it has one that isn't
+    // solid throughout, else from its red channel, in every channel.
+    pub fn load_opacity(path: &str) -> Result<ImageTexture, String> {
+        let lower = path.to_lowercase();
+        if lower.ends_with(".hdr") || lower.ends_with(".pic") || lower.ends_with(".exr") {
+            return ImageTexture::load(path, false);
+        }
+
+        let image = Image::load_image(path)?;
+        let colors = image.get_image_data();
+        let has_alpha = colors.iter().any(|c| c.a < 255);
+        let pixels = colors
+            .iter()
+            .map(|c| {
+                let value = if has_alpha { c.a } else { c.r } as f32 / 255.0;
+                Vec3::new(value, value, value)
+            })
+            .collect();
+
+        return Ok(ImageTexture::new(
+            path,
+            HdrImage {
+                width: image.width() as usize,
+                height: image.height() as usize,
+                pixels,
+            },
+        ));
+    }
+
+    fn texel(&self, x: i64, y: i64) -> Vec3 {
+        let x = x.rem_euclid(self.image.width as i64) as usize;
+        let y = y.rem_euclid(self.image.height as i64) as usize;
+        return self.image.pixels[y * self.image.width + x];
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f32, v: f32, _point: Vec3) -> Vec3 {
+        if self.image.pixels.is_empty() {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+
+        let x = u * self.image.width as f32 - 0.5;
+        let y = (1.0 - v) * self.image.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = (1.0 - tx) * self.texel(x0, y0) + tx * self.texel(x0 + 1, y0);
+        let bottom = (1.0 - tx) * self.texel(x0, y0 + 1) + tx * self.texel(x0 + 1, y0 + 1);
+        return (1.0 - ty) * top + ty * bottom;
+    }
+
+    fn describe(&self) -> String {
+        return format!("image {}", self.source);
+    }
+}
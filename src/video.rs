@@ -0,0 +1,367 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+// Receives the frames of an animation as RGB8 with row 0 at the top.
+pub trait FrameWriter {
+    fn write_frame(&mut self, rgb: &[u8]) -> io::Result<()>;
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+// "-" writes to stdout so frames can be piped into an encoder.
+pub fn open_output(path: &str) -> io::Result<Box<dyn Write>> {
+    if path == "-" {
+        return Ok(Box::new(BufWriter::new(io::stdout())));
+    }
+    return Ok(Box::new(BufWriter::new(File::create(path)?)));
+}
+
+// Opens a writer for the given format (gif, y4m or raw) at path.
+pub fn create(
+    format: &str,
+    path: &str,
+    width: usize,
+    height: usize,
+    fps: f32,
+) -> io::Result<Box<dyn FrameWriter>> {
+    let out = open_output(path)?;
+    return Ok(match format {
+        "gif" => Box::new(GifWriter::new(out, width, height, fps)?),
+        "y4m" => Box::new(Y4mWriter::new(out, width, height, fps)?),
+        "raw" => Box::new(RawWriter::new(out)),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown video format {}", format),
+            ))
+        }
+    });
+}
+
+// Headerless RGB24 frames, one after another.
+pub struct RawWriter {
+    out: Box<dyn Write>,
+}
+
+impl RawWriter {
+    pub fn new(out: Box<dyn Write>) -> Self {
+        Self { out }
+    }
+}
+
+impl FrameWriter for RawWriter {
+    fn write_frame(&mut self, rgb: &[u8]) -> io::Result<()> {
+        return self.out.write_all(rgb);
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        return self.out.flush();
+    }
+}
+
+// YUV4MPEG2 with full resolution chroma, BT.601 limited range, which most
+// encoders read without any extra flags.
+pub struct Y4mWriter {
+    out: Box<dyn Write>,
+    width: usize,
+    height: usize,
+}
+
+impl Y4mWriter {
+    pub fn new(mut out: Box<dyn Write>, width: usize, height: usize, fps: f32) -> io::Result<Self> {
+        let (numerator, denominator) = if fps.fract() == 0.0 {
+            (fps as u32, 1)
+        } else {
+            ((fps * 1000.0).round() as u32, 1000)
+        };
+        writeln!(
+            out,
+            "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444",
+            width, height, numerator, denominator
+        )?;
+
+        return Ok(Self { out, width, height });
+    }
+}
+
+impl FrameWriter for Y4mWriter {
+    fn write_frame(&mut self, rgb: &[u8]) -> io::Result<()> {
+        let n = self.width * self.height;
+        let mut planes = vec![0u8; 3 * n];
+
+        for i in 0..n {
+            let r = rgb[3 * i] as f32;
+            let g = rgb[3 * i + 1] as f32;
+            let b = rgb[3 * i + 2] as f32;
+
+            let y = 16.0 + (65.481 * r + 128.553 * g + 24.966 * b) / 255.0;
+            let u = 128.0 + (-37.797 * r - 74.203 * g + 112.0 * b) / 255.0;
+            let v = 128.0 + (112.0 * r - 93.786 * g - 18.214 * b) / 255.0;
+
+            planes[i] = y.round() as u8;
+            planes[n + i] = u.round() as u8;
+            planes[2 * n + i] = v.round() as u8;
+        }
+
+        self.out.write_all(b"FRAME\n")?;
+        return self.out.write_all(&planes);
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        return self.out.flush();
+    }
+}
+
+// Looping animated GIF. Each frame gets its own 256 color palette from a
+// median cut of its colors.
+pub struct GifWriter {
+    out: Box<dyn Write>,
+    width: usize,
+    height: usize,
+    // Frame duration in hundredths of a second.
+    delay: u16,
+}
+
+impl GifWriter {
+    pub fn new(mut out: Box<dyn Write>, width: usize, height: usize, fps: f32) -> io::Result<Self> {
+        if width > u16::MAX as usize || height > u16::MAX as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the image is too large for a GIF",
+            ));
+        }
+
+        out.write_all(b"GIF89a")?;
+        out.write_all(&(width as u16).to_le_bytes())?;
+        out.write_all(&(height as u16).to_le_bytes())?;
+        // No global color table, background 0, square pixels.
+        out.write_all(&[0x00, 0x00, 0x00])?;
+
+        // Loop forever.
+        out.write_all(&[0x21, 0xff, 0x0b])?;
+        out.write_all(b"NETSCAPE2.0")?;
+        out.write_all(&[0x03, 0x01, 0x00, 0x00, 0x00])?;
+
+        // Viewers treat delays below 2 as 10, so that is the fastest a GIF gets.
+        let delay = f32::max((100.0 / fps).round(), 2.0) as u16;
+
+        return Ok(Self {
+            out,
+            width,
+            height,
+            delay,
+        });
+    }
+}
+
+impl FrameWriter for GifWriter {
+    fn write_frame(&mut self, rgb: &[u8]) -> io::Result<()> {
+        let (palette, indices) = quantize(rgb);
+
+        // Graphic control extension: keep the frame, no transparency.
+        self.out.write_all(&[0x21, 0xf9, 0x04, 0x04])?;
+        self.out.write_all(&self.delay.to_le_bytes())?;
+        self.out.write_all(&[0x00, 0x00])?;
+
+        // Image descriptor with a local color table of 256 entries.
+        self.out.write_all(&[0x2c, 0x00, 0x00, 0x00, 0x00])?;
+        self.out.write_all(&(self.width as u16).to_le_bytes())?;
+        self.out.write_all(&(self.height as u16).to_le_bytes())?;
+        self.out.write_all(&[0x87])?;
+        for i in 0..256 {
+            let color = palette.get(i).copied().unwrap_or([0, 0, 0]);
+            self.out.write_all(&color)?;
+        }
+
+        self.out.write_all(&[8])?;
+        for block in lzw_encode(&indices).chunks(255) {
+            self.out.write_all(&[block.len() as u8])?;
+            self.out.write_all(block)?;
+        }
+        return self.out.write_all(&[0x00]);
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.write_all(&[0x3b])?;
+        return self.out.flush();
+    }
+}
+
+// Colors are binned to 5 bits per channel before the median cut, which keeps
+// both the cut and the mapping of pixels to the palette cheap.
+fn bin(r: u8, g: u8, b: u8) -> usize {
+    return ((r as usize >> 3) << 10) | ((g as usize >> 3) << 5) | (b as usize >> 3);
+}
+
+// Median cut (Heckbert 1982, "Color Image Quantization for Frame Buffer
+// Display"). Returns the palette and the palette index of every pixel.
+fn quantize(rgb: &[u8]) -> (Vec<[u8; 3]>, Vec<u8>) {
+    let mut count = vec![0u32; 1 << 15];
+    let mut sum = vec![[0u64; 3]; 1 << 15];
+    for pixel in rgb.chunks(3) {
+        let i = bin(pixel[0], pixel[1], pixel[2]);
+        count[i] += 1;
+        for c in 0..3 {
+            sum[i][c] += pixel[c] as u64;
+        }
+    }
+
+    // Mean color of every occupied bin.
+    let mut colors: Vec<(usize, [u8; 3])> = Vec::new();
+    for i in 0..count.len() {
+        if count[i] > 0 {
+            let n = count[i] as u64;
+            let mean = [
+                (sum[i][0] / n) as u8,
+                (sum[i][1] / n) as u8,
+                (sum[i][2] / n) as u8,
+            ];
+            colors.push((i, mean));
+        }
+    }
+
+    // Each box is a range of colors; the widest splittable box is cut at the
+    // pixel weighted median of its widest channel until there are 256.
+    let mut boxes = vec![(0, colors.len())];
+    while boxes.len() < 256 {
+        let mut widest: Option<(usize, usize, u8)> = None;
+        for (b, &(start, end)) in boxes.iter().enumerate() {
+            if end - start < 2 {
+                continue;
+            }
+            for c in 0..3 {
+                let low = colors[start..end].iter().map(|e| e.1[c]).min().unwrap();
+                let high = colors[start..end].iter().map(|e| e.1[c]).max().unwrap();
+                if widest.is_none_or(|w| high - low > w.2) {
+                    widest = Some((b, c, high - low));
+                }
+            }
+        }
+
+        let (b, c) = match widest {
+            Some((b, c, _)) => (b, c),
+            None => break,
+        };
+        let (start, end) = boxes[b];
+        colors[start..end].sort_by_key(|e| e.1[c]);
+
+        let total: u32 = colors[start..end].iter().map(|e| count[e.0]).sum();
+        let mut seen = 0;
+        let mut split = start + 1;
+        for i in start..end - 1 {
+            seen += count[colors[i].0];
+            split = i + 1;
+            if 2 * seen >= total {
+                break;
+            }
+        }
+
+        boxes[b] = (start, split);
+        boxes.push((split, end));
+    }
+
+    let mut palette = Vec::with_capacity(boxes.len());
+    for &(start, end) in &boxes {
+        let mut total = [0u64; 3];
+        let mut n = 0;
+        for e in &colors[start..end] {
+            for c in 0..3 {
+                total[c] += sum[e.0][c];
+            }
+            n += count[e.0] as u64;
+        }
+        palette.push([
+            (total[0] / n.max(1)) as u8,
+            (total[1] / n.max(1)) as u8,
+            (total[2] / n.max(1)) as u8,
+        ]);
+    }
+
+    // Every bin maps to the nearest palette color.
+    let mut nearest = vec![0u8; 1 << 15];
+    for &(i, color) in &colors {
+        let mut best = (u32::MAX, 0);
+        for (p, entry) in palette.iter().enumerate() {
+            let mut d = 0;
+            for c in 0..3 {
+                let delta = color[c] as i32 - entry[c] as i32;
+                d += (delta * delta) as u32;
+            }
+            if d < best.0 {
+                best = (d, p);
+            }
+        }
+        nearest[i] = best.1 as u8;
+    }
+
+    let indices = rgb
+        .chunks(3)
+        .map(|pixel| nearest[bin(pixel[0], pixel[1], pixel[2])])
+        .collect();
+    return (palette, indices);
+}
+
+// Variable length LZW as GIF uses it, for 8 bit indices.
+fn lzw_encode(indices: &[u8]) -> Vec<u8> {
+    const CLEAR: u16 = 256;
+    const END: u16 = 257;
+
+    let mut out = Vec::new();
+    let mut bits: u32 = 0;
+    let mut bit_count = 0;
+    let mut emit = |code: u16, size: u32, out: &mut Vec<u8>| {
+        bits |= (code as u32) << bit_count;
+        bit_count += size;
+        while bit_count >= 8 {
+            out.push(bits as u8);
+            bits >>= 8;
+            bit_count -= 8;
+        }
+    };
+
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut code_size = 9;
+    let mut next_code = END + 1;
+    emit(CLEAR, code_size, &mut out);
+
+    let mut prefix = match indices.first() {
+        Some(&first) => first as u16,
+        None => {
+            emit(END, code_size, &mut out);
+            if bit_count > 0 {
+                out.push(bits as u8);
+            }
+            return out;
+        }
+    };
+
+    for &index in &indices[1..] {
+        if let Some(&code) = table.get(&(prefix, index)) {
+            prefix = code;
+            continue;
+        }
+
+        emit(prefix, code_size, &mut out);
+        table.insert((prefix, index), next_code);
+        if next_code >= 1 << code_size {
+            code_size += 1;
+        }
+        next_code += 1;
+        prefix = index as u16;
+
+        // The table is full, start over.
+        if next_code == 4095 {
+            emit(CLEAR, code_size, &mut out);
+            table.clear();
+            code_size = 9;
+            next_code = END + 1;
+        }
+    }
+
+    emit(prefix, code_size, &mut out);
+    emit(END, code_size, &mut out);
+    if bit_count > 0 {
+        out.push(bits as u8);
+    }
+    return out;
+}